@@ -0,0 +1,483 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Cell, Variant};
+use crate::config;
+use crate::events::{Move, Notation, Turn};
+use crate::protocol::{self, End, Outcome};
+use crate::setup::{self, Setup};
+use crate::transcript;
+
+// Every game finished on this machine, kept in `games.jsonl` next to the
+// config file, one JSON object a line, oldest first.
+//
+// Each game carries a key for every position it went through, so finding
+// the games that reached a position doesn't mean playing them all out
+// again. Positions that are the same but for turning or mirroring the
+// board share a key.
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u32,
+    // Seconds since the Unix epoch.
+    pub time: u64,
+    pub black: String,
+    pub white: String,
+    // `black`, `white` or `draw`.
+    pub winner: String,
+    // `finished`, `resigned` or `time`.
+    pub end: String,
+    pub discs: [u16; 2],
+    pub variant: Variant,
+    pub size: usize,
+    // The starting position, if it isn't the usual one.
+    pub start: Option<String>,
+    // In standard notation, whatever the game was played in.
+    pub moves: String,
+    // The `key` of every position, the start first.
+    pub positions: Vec<String>,
+}
+
+// What to look for. Everything given has to match.
+#[derive(Default)]
+pub struct Query {
+    // Plays either side.
+    pub player: Option<String>,
+    // `black`, `white`, `draw`, or the name of whoever won.
+    pub winner: Option<String>,
+    // The first second counted, and the first one after.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // How many moves in a position came up, and its key.
+    pub opening: Option<(usize, String)>,
+    // The key of a position that came up at any point.
+    pub reached: Option<String>,
+}
+
+impl Entry {
+    // Puts together the record of a game that ended with `outcome`.
+    pub fn new(
+        black: &str,
+        white: &str,
+        outcome: &Outcome,
+        start: &Setup,
+        moves: &[Move],
+    ) -> Result<Self, String> {
+        let moves: Vec<Move> = moves
+            .iter()
+            .copied()
+            .filter(|mv| matches!(mv, Move::Play(_, _) | Move::Pass))
+            .collect();
+        let positions = transcript::replay_from(start, &moves)?;
+        Ok(Self {
+            id: 0,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            black: black.to_string(),
+            white: white.to_string(),
+            winner: protocol::winner_name(outcome.winner).to_string(),
+            end: outcome.end.name().to_string(),
+            discs: [outcome.black, outcome.white],
+            variant: config::current().variant,
+            size: start.board.size,
+            start: Some(start.format()).filter(|_| !start.is_standard()),
+            moves: transcript::format(&moves, Notation::Standard),
+            positions: positions.iter().map(|p| key(&p.board, p.turn)).collect(),
+        })
+    }
+
+    pub fn moves(&self) -> Result<Vec<Move>, String> {
+        let mut moves = Vec::new();
+        for token in self.moves.split_whitespace() {
+            if token == "pass" {
+                moves.push(Move::Pass);
+                continue;
+            }
+            match Notation::Standard.parse(token, self.size) {
+                Some(squares) => moves.extend(squares.into_iter().map(|(r, c)| Move::Play(r, c))),
+                None => return Err(format!("game {}: bad move `{}`", self.id, token)),
+            }
+        }
+        Ok(moves)
+    }
+
+    // Whoever won, by name, if anyone did.
+    fn winner_name(&self) -> Option<&str> {
+        match self.winner.as_str() {
+            "black" => Some(&self.black),
+            "white" => Some(&self.white),
+            _ => None,
+        }
+    }
+
+    // How the game went, like `White wins 24-40` or `Black wins on time`.
+    fn result(&self) -> String {
+        let [black, white] = self.discs;
+        let side = match self.winner.as_str() {
+            "black" => "Black wins",
+            "white" => "White wins",
+            _ => "Draw",
+        };
+        match End::parse(&self.end) {
+            Some(End::Resigned) => format!("{} by resignation", side),
+            Some(End::Time) => format!("{} on time", side),
+            Some(End::Forfeit) => format!("{} by forfeit", side),
+            _ => format!("{} {}-{}", side, black, white),
+        }
+    }
+}
+
+impl Query {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let player = self
+            .player
+            .as_ref()
+            .is_none_or(|name| entry.black == *name || entry.white == *name);
+        let winner = self
+            .winner
+            .as_ref()
+            .is_none_or(|w| entry.winner == *w || entry.winner_name() == Some(w.as_str()));
+        let since = self.since.is_none_or(|since| entry.time >= since);
+        let until = self.until.is_none_or(|until| entry.time < until);
+        let opening = self
+            .opening
+            .as_ref()
+            .is_none_or(|(ply, key)| entry.positions.get(*ply) == Some(key));
+        let reached = self
+            .reached
+            .as_ref()
+            .is_none_or(|key| entry.positions.contains(key));
+        player && winner && since && until && opening && reached
+    }
+
+    // Looks for games that opened with `moves`, played from this run's
+    // start, or anything that comes to the same position.
+    pub fn opening(&mut self, moves: &[Move]) -> Result<(), String> {
+        let positions = transcript::replay(moves)?;
+        let last = positions.last().expect("starting position");
+        self.opening = Some((positions.len() - 1, key(&last.board, last.turn)));
+        Ok(())
+    }
+}
+
+// The same for a position and its seven reflections and rotations: the
+// smallest of their position strings, hashed.
+pub fn key(board: &Board, turn: Turn) -> String {
+    let n = board.size;
+    let side = if turn == Turn::White { 'O' } else { 'X' };
+    let canonical = (0..8)
+        .map(|symmetry| {
+            let mut text = String::with_capacity(n * n + 1);
+            for row in 1..=n {
+                for col in 1..=n {
+                    let (mut r, mut c) = (row, col);
+                    if symmetry & 4 != 0 {
+                        std::mem::swap(&mut r, &mut c);
+                    }
+                    if symmetry & 1 != 0 {
+                        r = n + 1 - r;
+                    }
+                    if symmetry & 2 != 0 {
+                        c = n + 1 - c;
+                    }
+                    text.push(match board.cells[r][c] {
+                        Cell::Black => 'X',
+                        Cell::White => 'O',
+                        Cell::Blocked => '#',
+                        _ => '-',
+                    });
+                }
+            }
+            text.push(side);
+            text
+        })
+        .min()
+        .expect("eight symmetries");
+    // FNV-1a, which unlike the standard library's hasher is the same
+    // from one build to the next.
+    let hash = canonical
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+// Where the archive is kept, in the config directory.
+pub const FILE: &str = "games.jsonl";
+
+pub fn path() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join(FILE))
+}
+
+// A file that isn't there yet is an empty archive.
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    lines
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+// Adds a game to the end of the archive, numbered after the last one.
+pub fn append(path: &Path, entry: &mut Entry) -> Result<u32, String> {
+    let add = |entry: &mut Entry| -> io::Result<()> {
+        let last = match fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .and_then(|line| serde_json::from_str::<Entry>(line).ok()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        entry.id = last.map_or(1, |last| last.id + 1);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let line = serde_json::to_string(entry).expect("archive entry serializes");
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    };
+    add(entry).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(entry.id)
+}
+
+// Archives a game played from this run's start, returning its number.
+pub fn store(
+    black: &str,
+    white: &str,
+    outcome: &Outcome,
+    moves: &[Move],
+) -> Result<Option<u32>, String> {
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let mut entry = Entry::new(black, white, outcome, setup::start(), moves)?;
+    append(&path, &mut entry).map(Some)
+}
+
+// Reads `YYYY-MM-DD` into days since the Unix epoch.
+pub fn parse_date(text: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid date `{}` (expected YYYY-MM-DD)", text);
+    let parts: Vec<_> = text.split('-').map(str::parse::<u32>).collect();
+    let (year, month, day) = match parts[..] {
+        [Ok(year), Ok(month), Ok(day)] if year >= 1970 => (year, month, day),
+        _ => return Err(invalid()),
+    };
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) == (year, month, day) {
+        Ok(days)
+    } else {
+        Err(invalid())
+    }
+}
+
+fn format_date(time: u64) -> String {
+    let (year, month, day) = civil_from_days(time / SECONDS_PER_DAY);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Howard Hinnant's algorithms, for days from 1970 on.
+fn days_from_civil(year: u32, month: u32, day: u32) -> u64 {
+    let year = u64::from(if month <= 2 { year - 1 } else { year });
+    let (era, year_of_era) = (year / 400, year % 400);
+    let month = u64::from((month + 9) % 12);
+    let day_of_year = (153 * month + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: u64) -> (u32, u32, u32) {
+    let days = days + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year as u32, month as u32, day as u32)
+}
+
+// `othello games`: lists the games that match.
+pub fn list(query: &Query) -> Result<(), String> {
+    let path = path().ok_or("no home directory to keep games in")?;
+    let games: Vec<_> = load(&path)?
+        .into_iter()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    if games.is_empty() {
+        println!("No games found.");
+        return Ok(());
+    }
+    let width = |side: fn(&Entry) -> &String| {
+        games
+            .iter()
+            .map(|g| side(g).len())
+            .max()
+            .unwrap_or(0)
+            .max(5)
+    };
+    let (black, white) = (width(|g| &g.black), width(|g| &g.white));
+    println!(
+        "  {:>4}  {:<10}  {:<black$}  {:<white$}  {:<24}  {:>5}",
+        "#",
+        "Date",
+        "Black",
+        "White",
+        "Result",
+        "Moves",
+        black = black,
+        white = white
+    );
+    for game in &games {
+        println!(
+            "  {:>4}  {:<10}  {:<black$}  {:<white$}  {:<24}  {:>5}",
+            game.id,
+            format_date(game.time),
+            game.black,
+            game.white,
+            game.result(),
+            game.positions.len() - 1,
+            black = black,
+            white = white
+        );
+    }
+    Ok(())
+}
+
+// `othello games --show <id>`: prints a game so that `othello replay`
+// and `othello play --load` can read it.
+pub fn show(id: u32) -> Result<(), String> {
+    let path = path().ok_or("no home directory to keep games in")?;
+    let games = load(&path)?;
+    let game = games
+        .iter()
+        .find(|game| game.id == id)
+        .ok_or_else(|| format!("no game {} in the archive", id))?;
+    if let Some(start) = &game.start {
+        println!("{}", start);
+    }
+    println!(
+        "{}",
+        transcript::format(&game.moves()?, config::current().notation)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(text: &str) -> Entry {
+        let moves = transcript::parse(text, Notation::Standard).unwrap();
+        let outcome = Outcome {
+            end: End::Resigned,
+            winner: Turn::White,
+            black: 0,
+            white: 0,
+        };
+        Entry::new("Alice", "ai:3", &outcome, &Setup::standard(8), &moves).unwrap()
+    }
+
+    #[test]
+    fn finds_positions_whatever_way_round_the_board_is() {
+        // The four first moves are one move turned about the board.
+        let keys: Vec<_> = ["f5", "d3", "c4", "e6"]
+            .iter()
+            .map(|mv| play(mv).positions[1].clone())
+            .collect();
+        assert!(keys.iter().all(|key| *key == keys[0]));
+        assert_ne!(play("f5 d6").positions[2], play("f5 f6").positions[2]);
+
+        let game = play("f5 d6 c3 d3 c4");
+        let mut query = Query::default();
+        let opening = transcript::parse("f5 f6", Notation::Standard).unwrap();
+        query.opening(&opening).unwrap();
+        assert!(!query.matches(&game));
+        let opening = transcript::parse("f5 d6 c3", Notation::Standard).unwrap();
+        query.opening(&opening).unwrap();
+        assert!(query.matches(&game));
+        // The same opening, mirrored along the diagonal and turned around.
+        for mirrored in &["e6 f4 c3", "c4 e3 f6"] {
+            let opening = transcript::parse(mirrored, Notation::Standard).unwrap();
+            query.opening(&opening).unwrap();
+            assert!(query.matches(&game));
+        }
+
+        let reached = Query {
+            reached: Some(game.positions[3].clone()),
+            ..Query::default()
+        };
+        assert!(reached.matches(&game));
+        assert!(!reached.matches(&play("f5 f6")));
+    }
+
+    #[test]
+    fn picks_games_by_player_result_and_date() {
+        let game = play("f5");
+        let query = |query: Query| query.matches(&game);
+        assert!(query(Query {
+            player: Some("ai:3".to_string()),
+            winner: Some("white".to_string()),
+            ..Query::default()
+        }));
+        assert!(query(Query {
+            winner: Some("ai:3".to_string()),
+            ..Query::default()
+        }));
+        assert!(!query(Query {
+            winner: Some("Alice".to_string()),
+            ..Query::default()
+        }));
+        assert!(!query(Query {
+            player: Some("Bob".to_string()),
+            ..Query::default()
+        }));
+        assert!(!query(Query {
+            until: Some(parse_date("2000-01-01").unwrap() * SECONDS_PER_DAY),
+            ..Query::default()
+        }));
+
+        assert_eq!(parse_date("1970-01-01"), Ok(0));
+        assert_eq!(parse_date("2024-03-01"), Ok(19783));
+        assert_eq!(format_date(19783 * SECONDS_PER_DAY + 5), "2024-03-01");
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+
+    #[test]
+    fn keeps_games_in_a_file() {
+        let dir = std::env::temp_dir().join(format!("othello-archive-{}", std::process::id()));
+        let path = dir.join("games.jsonl");
+        assert!(load(&path).unwrap().is_empty());
+        assert_eq!(append(&path, &mut play("f5 d6")).unwrap(), 1);
+        assert_eq!(append(&path, &mut play("f5 f6 e6")).unwrap(), 2);
+
+        let games = load(&path).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].moves, "f5 f6 e6");
+        assert_eq!(games[1].moves().unwrap().len(), 3);
+        assert!(games[1].start.is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,57 @@
+use std::cmp::Ordering;
 use std::fmt;
+use std::io::{self, IsTerminal};
 
-use colored::Colorize;
+use clap::ValueEnum;
+use colored::{ColoredString, Colorize};
+use serde::{Deserialize, Serialize};
 
+use crate::clock::Clock;
+use crate::config;
 use crate::events::{Move, Turn};
+use crate::setup;
+use crate::theme::{self, Style};
 
-pub const SIZE: usize = 9; // HEIGHT = WIDTH = 8, but +1 for labels.
+// How many squares across a board can be. Sizes must be even, so that
+// the starting discs sit right in the middle.
+pub const MIN_SIZE: usize = 4;
+pub const MAX_SIZE: usize = 16;
+
+// Checks a board size given with `--size` or in the config file.
+pub fn check_size(size: usize) -> Result<usize, String> {
+    if size.is_multiple_of(2) && (MIN_SIZE..=MAX_SIZE).contains(&size) {
+        Ok(size)
+    } else {
+        Err(format!(
+            "board size must be even and {} to {}, not {}",
+            MIN_SIZE, MAX_SIZE, size
+        ))
+    }
+}
+
+// The rules being played. In `Anti`, also known as reverse or misère
+// Othello, the player with fewer discs at the end wins.
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Othello,
+    Anti,
+}
+
+impl Variant {
+    // Who wins with these final disc counts, or `Neither` for a tie.
+    pub fn winner(self, black: u16, white: u16) -> Turn {
+        let (black, white) = match self {
+            Variant::Othello => (black, white),
+            Variant::Anti => (white, black),
+        };
+        match black.cmp(&white) {
+            Ordering::Greater => Turn::Black,
+            Ordering::Less => Turn::White,
+            Ordering::Equal => Turn::Neither,
+        }
+    }
+}
 
 #[derive(Clone, PartialEq)]
 pub enum Cell {
@@ -12,197 +59,282 @@ pub enum Cell {
     White,
     Okay,
     Illegal,
-    Label(char),
+    // A hole in the board, never played on and never flipped through.
+    Blocked,
+    Label(&'static str),
     Indicator(Turn),
 }
 
-// Might not work well if you have light themed terminal.
-// If that's the case, feel free to modify colors.
-// Or you can maybe simply apply dark theme. :)
+// Colors come from the theme picked with `--theme`.
+// See `theme.rs` for the built-in themes and how to add your own.
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let theme = theme::current();
+        let glyphs = theme::glyphs();
         match self {
-            // Feel free to change colors.
-            // Color reference can be found at:
-            // https://docs.rs/colored/2.0.0/colored/
-            Cell::Black => write!(f, "{}", "● ".black().on_green()),
-            Cell::White => write!(f, "{}", "● ".on_green()),
-            Cell::Okay => write!(f, "{}", "・".on_green()),
-            Cell::Illegal => write!(f, "{}", "・".on_green()),
-            Cell::Label(c) => write!(f, "{} ", *c),
+            // Boards with two-digit labels pad every cell to keep them apart.
+            Cell::Label(s) => write!(f, "{:<2}", s),
             Cell::Indicator(t) => match t {
-                Turn::Black => write!(f, "{}", "● ".green()),
-                Turn::White => write!(f, "{}", "○ ".green()),
+                Turn::Black => write!(f, "{}", theme.indicator.paint(glyphs.black)),
+                Turn::White => write!(f, "{}", theme.indicator.paint(glyphs.hollow_white)),
                 Turn::Neither => f.write_str("  "),
             },
+            _ => write!(f, "{}", self.highlighted(Style::default())),
         }
     }
 }
 
+impl Cell {
+    // Draws a cell with a highlight laid over its usual style, to pick out
+    // the last move, the discs it flipped or the cursor.
+    pub fn highlighted(&self, highlight: Style) -> ColoredString {
+        let theme = theme::current();
+        let glyphs = theme::glyphs();
+        let (style, glyph) = match self {
+            Cell::Black => (theme.black, glyphs.black),
+            Cell::White if theme.hollow_white => (theme.white, glyphs.hollow_white),
+            Cell::White => (theme.white, glyphs.white),
+            Cell::Okay | Cell::Illegal => (theme.empty, glyphs.empty),
+            Cell::Blocked => (theme.empty, glyphs.blocked),
+            _ => return self.to_string().normal(),
+        };
+        style.under(highlight).paint(glyph)
+    }
+}
+
+#[derive(Clone)]
 pub struct Board {
+    // Squares across, and down. `cells` is one bigger for the labels.
+    pub size: usize,
     pub cells: Vec<Vec<Cell>>,
+    pub hints: bool,
+    pub last_move: Option<(usize, usize)>,
+    pub flipped: Vec<(usize, usize)>,
 }
 
 impl Board {
+    // A board of the size picked with `--size`.
     pub fn new() -> Self {
+        Self::with_size(config::current().size)
+    }
+
+    pub fn with_size(size: usize) -> Self {
+        let mut board = Self::empty(size);
+        board.set_initial_state();
+        board.validate_cells();
+        board
+    }
+
+    // A board with labels, but no discs at all.
+    pub fn empty(size: usize) -> Self {
         let mut board = Self {
-            cells: vec![vec![Cell::Illegal; SIZE]; SIZE],
+            size,
+            cells: vec![vec![Cell::Illegal; size + 1]; size + 1],
+            hints: config::current().hints,
+            last_move: None,
+            flipped: Vec::new(),
         };
-        board.set_initial_state_and_label();
-        board.validate_cells();
+        board.set_label();
         board
     }
 
-    pub fn draw(&self, turn: Turn, mv: Option<Move>) {
-        print!("\x1B[2J\x1B[1;1H");
-        for row in self.cells.iter() {
-            for col in row {
-                print!("{}", col);
+    pub fn draw(&self, turn: Turn, mv: Option<Move>, clock: Option<&Clock>) {
+        // Clearing the screen only makes sense on a terminal.
+        // Anywhere else, like a log, each board simply follows the last.
+        if io::stdout().is_terminal() {
+            print!("\x1B[2J\x1B[1;1H");
+        } else {
+            println!();
+        }
+        self.print(turn, mv, clock);
+    }
+
+    // Prints the board and everything under it, without clearing anything first.
+    pub fn print(&self, turn: Turn, mv: Option<Move>, clock: Option<&Clock>) {
+        let legal = self.hints_for(turn, mv);
+        for (r, row) in self.cells.iter().enumerate() {
+            for c in 0..row.len() {
+                print!("{}", self.render(r, c, &legal));
             }
             println!();
         }
+        println!("{}", self.status());
+        if let Some(clock) = clock {
+            println!("{}", clock);
+        }
+        if let Some(message) = message(turn, mv) {
+            println!("{}", message);
+        }
+    }
+
+    // Legal moves to point out, if hints are on.
+    // They are only worth showing while the game is still on.
+    pub fn hints_for(&self, turn: Turn, mv: Option<Move>) -> Vec<(usize, usize)> {
         match mv {
-            Some(Move::Pass) => match turn {
-                Turn::Black => println!("{}", "White passed.".yellow()),
-                Turn::White => println!("{}", "Black passed.".yellow()),
-                _ => {}
-            },
-            Some(Move::Skip) => match turn {
-                Turn::Black => {
-                    println!(
-                        "{}",
-                        "Couln't find available moves for White.\nSkipping it's turn...".yellow()
-                    )
-                }
-                Turn::White => {
-                    println!(
-                        "{}",
-                        "Couln't find available moves for Black.\nSkipping it's turn...".yellow()
-                    )
-                }
-                _ => {}
-            },
-            Some(Move::Resign) => match turn {
-                Turn::Black => println!("{}", "Black has resigned.\nWhite wins.".purple()),
-                Turn::White => println!("{}", "White has resigned.\nBlack wins.".purple()),
-                _ => {}
-            },
-            Some(Move::Win(b, w)) => match turn {
-                Turn::Black => {
-                    println!("{}", format!("Black wins by {} points.", b - w).purple())
-                }
-                Turn::White => {
-                    println!("{}", format!("White wins by {} points.", w - b).purple())
+            None | Some(Move::Pass) | Some(Move::Skip) if self.hints => {
+                Move::legal_moves(self, turn)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Renders one cell, picking out the last move, the discs it flipped
+    // and any of the `legal` moves.
+    pub fn render(&self, row: usize, col: usize, legal: &[(usize, usize)]) -> String {
+        let cell = &self.cells[row][col];
+        let theme = theme::current();
+        if self.last_move == Some((row, col)) {
+            self.pad(cell.highlighted(theme.last_move))
+        } else if self.flipped.contains(&(row, col)) {
+            self.pad(cell.highlighted(theme.flipped))
+        } else if legal.contains(&(row, col)) {
+            self.pad(theme.legal.paint(theme::glyphs().legal))
+        } else {
+            self.pad(cell)
+        }
+    }
+
+    // Columns per cell: two, or three once labels like `10` would
+    // otherwise run into the next one.
+    pub fn cell_width(&self) -> usize {
+        if self.size >= 10 {
+            3
+        } else {
+            2
+        }
+    }
+
+    // Fills a drawn cell out to the board's `cell_width`.
+    pub fn pad(&self, cell: impl fmt::Display) -> String {
+        format!("{}{:2$}", cell, "", self.cell_width() - 2)
+    }
+
+    // Compares the board against how it was `before` the last turn, and
+    // remembers where a disc was placed and which discs it flipped.
+    // Turns that placed nothing, like passes, leave the last move as it was.
+    pub fn record_changes(&mut self, before: &Board) {
+        let mut placed = None;
+        let mut flipped = Vec::new();
+        for row in 1..=self.size {
+            for col in 1..=self.size {
+                match (&before.cells[row][col], &self.cells[row][col]) {
+                    (Cell::Okay, Cell::Black | Cell::White) => placed = Some((row, col)),
+                    (Cell::Black, Cell::White) | (Cell::White, Cell::Black) => {
+                        flipped.push((row, col))
+                    }
+                    _ => {}
                 }
-                Turn::Neither => println!("TIE!!"),
-            },
-            _ => {}
+            }
+        }
+        if placed.is_some() {
+            self.last_move = placed;
+            self.flipped = flipped;
         }
     }
 
-    fn set_initial_state_and_label(&mut self) {
+    // Which move is next in a game that started from `start`. Every move
+    // places exactly one disc, on top of those the game started with.
+    pub fn move_number(&self, start: &Board) -> usize {
+        let discs = |board: &Board| {
+            let (black, white) = board.count_discs();
+            black as usize + white as usize
+        };
+        (discs(self) + 1).saturating_sub(discs(start))
+    }
+
+    // Live disc counts, move number, empties and mobility, shown under the board.
+    pub fn status(&self) -> String {
+        let (black, white) = self.count_discs();
+        let empties = self.empties();
+        let move_number = self.move_number(&setup::start().board);
+        let (b, w) = (theme::glyphs().black_mark, theme::glyphs().white_mark);
+        format!(
+            "{} {:<2} {} {:<2}  Move {:<2}  Empties {:<2}  Mobility {} {} {} {}",
+            b,
+            black,
+            w,
+            white,
+            move_number,
+            empties,
+            b,
+            Move::legal_moves(self, Turn::Black).len(),
+            w,
+            Move::legal_moves(self, Turn::White).len(),
+        )
+    }
+
+    fn set_initial_state(&mut self) {
+        let mid = self.size / 2;
+        self.cells[mid][mid] = Cell::White; // Initial position of White.
+        self.cells[mid + 1][mid + 1] = Cell::White; // Initial position of White.
+        self.cells[mid][mid + 1] = Cell::Black; // Initial position of Black
+        self.cells[mid + 1][mid] = Cell::Black; // Initial position of Black
+    }
+
+    fn set_label(&mut self) {
         self.cells[0][0] = Cell::Indicator(Turn::Black); // Indicator located at top left corner.
-        self.cells[4][4] = Cell::White; // Initial position of White.
-        self.cells[5][5] = Cell::White; // Initial position of White.
-        self.cells[4][5] = Cell::Black; // Initial position of Black
-        self.cells[5][4] = Cell::Black; // Initial position of Black
 
         //# Labeling:
-        for (i, c) in ('1'..='8').enumerate() {
-            self.cells[0][i + 1] = Cell::Label(c);
+        let (across, down) = config::current().notation.labels(self.size);
+        for (i, label) in across.iter().enumerate() {
+            self.cells[0][i + 1] = Cell::Label(label);
         }
-        for (i, c) in ('A'..='H').enumerate() {
-            self.cells[i + 1][0] = Cell::Label(c);
+        for (i, label) in down.iter().enumerate() {
+            self.cells[i + 1][0] = Cell::Label(label);
         }
         //# Labeling ends.
     }
 
     // This function validates whether or not empty cells are okay to be played.
     pub fn validate_cells(&mut self) {
-        for row in 1..SIZE {
-            for col in 1..SIZE {
-                match self.cells[row][col] {
-                    Cell::Black | Cell::White => {
-                        match (row, col) {
-                            //# Corner edge cases:
-                            (1, 1) => {
-                                self.validate(1, 2); // R
-                                self.validate(2, 1); // D
-                                self.validate(2, 2); // DR
-                            }
-                            (1, 8) => {
-                                self.validate(1, 7); // L
-                                self.validate(2, 8); // D
-                                self.validate(2, 7); // DL
-                            }
-                            (8, 1) => {
-                                self.validate(8, 2); // R
-                                self.validate(7, 1); // U
-                                self.validate(7, 2); // UR
-                            }
-                            (8, 8) => {
-                                self.validate(8, 7); // L
-                                self.validate(7, 8); // U
-                                self.validate(7, 7); // UL
-                            }
-                            //# Corner edge cases end.
-                            (1, 2..=7) => {
-                                self.validate(row, col - 1); // L
-                                self.validate(row, col + 1); // R
-                                self.validate(row + 1, col); // D
-                                self.validate(row + 1, col - 1); // DL
-                                self.validate(row + 1, col + 1); // DR
-                            }
-                            (8, 2..=7) => {
-                                self.validate(row, col - 1); // L
-                                self.validate(row, col + 1); // R
-                                self.validate(row - 1, col); // U
-                                self.validate(row - 1, col - 1); // UL
-                                self.validate(row - 1, col + 1); // UR
-                            }
-                            (2..=7, 1) => {
-                                self.validate(row - 1, col); // U
-                                self.validate(row + 1, col); // D
-                                self.validate(row, col + 1); // R
-                                self.validate(row - 1, col + 1); // UR
-                                self.validate(row + 1, col + 1); // DR
-                            }
-                            (2..=7, 8) => {
-                                self.validate(row - 1, col); // U
-                                self.validate(row + 1, col); // D
-                                self.validate(row, col - 1); // L
-                                self.validate(row - 1, col - 1); // UL
-                                self.validate(row + 1, col - 1); // DL
-                            }
-                            _ => {
-                                self.validate(row - 1, col - 1); // UL
-                                self.validate(row - 1, col + 1); // UR
-                                self.validate(row + 1, col - 1); // DL
-                                self.validate(row + 1, col + 1); // DR
-                                self.validate(row - 1, col); // U
-                                self.validate(row + 1, col); // D
-                                self.validate(row, col - 1); // L
-                                self.validate(row, col + 1); // R
-                            }
+        for row in 1..=self.size {
+            for col in 1..=self.size {
+                if let Cell::Black | Cell::White = self.cells[row][col] {
+                    // Every neighbour that is on the board, edges and corners included.
+                    for r in row.max(2) - 1..=(row + 1).min(self.size) {
+                        for c in col.max(2) - 1..=(col + 1).min(self.size) {
+                            self.validate(r, c);
                         }
                     }
-                    _ => {}
                 }
             }
         }
     }
 
+    // Puts a disc on, or takes one off with `Cell::Illegal`, and works out
+    // again which empty cells are next to a disc.
+    pub fn set(&mut self, row: usize, col: usize, cell: Cell) {
+        self.cells[row][col] = cell;
+        for row in 1..=self.size {
+            for col in 1..=self.size {
+                if self.cells[row][col] == Cell::Okay {
+                    self.cells[row][col] = Cell::Illegal;
+                }
+            }
+        }
+        self.validate_cells();
+    }
+
     fn validate(&mut self, row: usize, col: usize) {
         if self.cells[row][col] == Cell::Illegal {
             self.cells[row][col] = Cell::Okay;
         }
     }
 
-    pub fn count_scores(&self) -> (bool, Turn, Option<u8>, Option<u8>) {
+    // Squares still to be played on, holes left out.
+    pub fn empties(&self) -> usize {
+        let squares = self.cells.iter().skip(1).flat_map(|row| row.iter().skip(1));
+        squares
+            .filter(|cell| matches!(cell, Cell::Okay | Cell::Illegal))
+            .count()
+    }
+
+    // Returns the number of (black, white) discs on the board.
+    pub fn count_discs(&self) -> (u16, u16) {
         let mut black_count = 0;
         let mut white_count = 0;
-        for row in 1..SIZE {
-            for col in 1..SIZE {
+        for row in 1..=self.size {
+            for col in 1..=self.size {
                 if self.cells[row][col] == Cell::Black {
                     black_count += 1;
                 } else if self.cells[row][col] == Cell::White {
@@ -210,13 +342,92 @@ impl Board {
                 }
             }
         }
+        (black_count, white_count)
+    }
 
-        if black_count > white_count {
-            (true, Turn::Black, Some(black_count), Some(white_count))
-        } else if black_count < white_count {
-            (true, Turn::White, Some(black_count), Some(white_count))
-        } else {
-            (true, Turn::Neither, Some(black_count), Some(white_count))
+    pub fn count_scores(&self) -> (bool, Turn, Option<u16>, Option<u16>) {
+        let (black_count, white_count) = self.count_discs();
+        let winner = config::current().variant.winner(black_count, white_count);
+        (true, winner, Some(black_count), Some(white_count))
+    }
+}
+
+// What to tell the players after `mv`, if anything.
+// `turn` is whoever is to move next, or whoever the game ended on.
+pub fn message(turn: Turn, mv: Option<Move>) -> Option<ColoredString> {
+    let theme = theme::current();
+    let name = |turn| config::current().name(turn);
+    let (you, them) = (name(turn), name(turn.opponent()));
+    let message = match mv {
+        Some(Move::Win(_, _)) if turn == Turn::Neither => "TIE!!".normal(),
+        _ if turn == Turn::Neither => return None,
+        Some(Move::Pass) => theme.info.paint(&format!("{} passed.", them)),
+        Some(Move::Skip) => theme.info.paint(&format!(
+            "Couln't find available moves for {}.\nSkipping it's turn...",
+            them
+        )),
+        Some(Move::Resign) => theme
+            .result
+            .paint(&format!("{} has resigned.\n{} wins.", you, them)),
+        Some(Move::Timeout) => theme
+            .result
+            .paint(&format!("{} ran out of time.\n{} wins.", you, them)),
+        Some(Move::Win(b, w)) => match config::current().variant {
+            Variant::Othello => {
+                theme
+                    .result
+                    .paint(&format!("{} wins by {} points.", you, b.abs_diff(w)))
+            }
+            Variant::Anti => {
+                theme
+                    .result
+                    .paint(&format!("{} wins with {} fewer discs.", you, b.abs_diff(w)))
+            }
+        },
+        _ => return None,
+    };
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::InputHandler;
+    use crate::setup::Setup;
+
+    #[test]
+    fn counts_moves_from_the_start_the_game_had() {
+        for start in [Setup::standard(8), Setup::random(8)] {
+            let board = &start.board;
+            assert_eq!(board.move_number(board), 1);
+            let (row, col) = Move::legal_moves(board, start.turn)[0];
+            let next = Move::after(board, start.turn, row, col).unwrap();
+            assert_eq!(next.move_number(board), 2);
         }
     }
+
+    #[test]
+    fn counts_past_255_discs() {
+        let start = Setup::standard(MAX_SIZE);
+        let (mut board, mut turn) = (start.board.clone(), start.turn);
+        let (black, white) = loop {
+            let (row, col) = Move::legal_moves(&board, turn)[0];
+            Move::is_valid_move(&mut board, turn, row, col);
+            match Move::apply_move(&mut board, turn, Move::Play(row, col)) {
+                (_, Some(Move::Win(black, white))) => break (black, white),
+                (next, _) => turn = next,
+            }
+        };
+        assert_eq!(board.count_discs(), (black, white));
+        assert_eq!(
+            black as usize + white as usize + board.empties(),
+            MAX_SIZE * MAX_SIZE
+        );
+        assert!(black as usize + white as usize > u8::MAX as usize);
+        assert_eq!(
+            board.move_number(&start.board),
+            black as usize + white as usize - 3
+        );
+        assert!(board.status().contains(&black.to_string()));
+    }
 }
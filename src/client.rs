@@ -0,0 +1,410 @@
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use crate::board::Board;
+use crate::clock::Remaining;
+use crate::config;
+use crate::events::{self, Move, Turn};
+use crate::protocol::{self, ClientMsg, End, Outcome, ServerMsg};
+use crate::setup::Setup;
+use crate::theme;
+
+// How long to keep trying to get back into a game after losing the
+// connection, and how often to try.
+const RETRY_FOR: Duration = Duration::from_secs(60);
+const RETRY_EVERY: Duration = Duration::from_secs(2);
+// How long to wait on the keyboard before looking for news from the server.
+const POLL: Duration = Duration::from_millis(100);
+// How many lines of what happened lately to show under the board.
+const LOG_LINES: usize = 5;
+
+// What to do once logged in.
+#[derive(Clone, Copy)]
+pub enum Lobby {
+    // Join the first open seek, or open one if there is none.
+    Any,
+    Seek(Turn),
+    Join(u32),
+    Watch(u32),
+}
+
+struct Client {
+    addr: String,
+    name: String,
+    // Given out by the server, to take our seat back with.
+    token: Option<String>,
+    lobby: Lobby,
+    // The open seeks heard about while looking for a game.
+    seeks: Vec<u32>,
+    game: Option<u32>,
+    // `Turn::Neither` when watching.
+    side: Option<Turn>,
+    // Black, then white.
+    names: [String; 2],
+    board: Option<Board>,
+    turn: Turn,
+    hints: bool,
+    log: Vec<String>,
+    outcome: Option<Outcome>,
+    // Milliseconds left for black and white, as of the last move.
+    clock: Option<[u64; 2]>,
+}
+
+// The name to play under when none is given.
+pub fn default_name() -> String {
+    env::var("USER").unwrap_or_else(|_| "player".to_string())
+}
+
+// `othello connect`: plays or watches a game hosted by `othello serve`
+// at `addr`.
+pub fn connect(addr: &str, name: &str, token: Option<String>, lobby: Lobby) -> Result<(), String> {
+    let mut client = Client {
+        addr: addr.to_string(),
+        name: name.to_string(),
+        token,
+        lobby,
+        seeks: Vec::new(),
+        game: None,
+        side: None,
+        names: ["Black".to_string(), "White".to_string()],
+        board: None,
+        turn: Turn::Black,
+        hints: config::current().hints,
+        log: Vec::new(),
+        outcome: None,
+        clock: None,
+    };
+    let (mut stream, mut msgs) = client.join()?;
+    loop {
+        let mut changed = false;
+        loop {
+            match msgs.try_recv() {
+                Ok(msg) => {
+                    for reply in client.receive(msg)? {
+                        let _ = writeln!(stream, "{}", reply);
+                    }
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) if client.outcome.is_some() => break,
+                Err(TryRecvError::Disconnected) => {
+                    (stream, msgs) = client.rejoin()?;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        if changed {
+            client.draw();
+        }
+        if client.outcome.is_some() {
+            return Ok(());
+        }
+
+        let line = match events::read_line(Some(Instant::now() + POLL)) {
+            Ok(Some(line)) => line,
+            Ok(None) => continue,
+            Err(_) => return client.leave(),
+        };
+        if let Some(text) = line.trim().strip_prefix("say ") {
+            let _ = writeln!(stream, "{}", ClientMsg::Say(text.trim().to_string()));
+            continue;
+        }
+        let size = client
+            .board
+            .as_ref()
+            .map_or(config::current().size, |b| b.size);
+        let msg = match events::parse_input(&line, config::current().notation, size) {
+            Ok(Move::Play(row, col)) => ClientMsg::Move(row, col),
+            Ok(Move::Pass) => ClientMsg::Pass,
+            Ok(Move::Resign) => ClientMsg::Resign,
+            Ok(Move::Quit) => return client.leave(),
+            Ok(_) => {
+                client.hints = !client.hints;
+                client.draw();
+                continue;
+            }
+            Err(e) => {
+                client.note(
+                    theme::current()
+                        .error
+                        .paint(&format!("Invalid input: {}.", e))
+                        .to_string(),
+                );
+                client.draw();
+                continue;
+            }
+        };
+        // If this fails, the reader finds out the connection is gone.
+        let _ = writeln!(stream, "{}", msg);
+    }
+}
+
+impl Client {
+    // Connects and takes a seat, and reads what the server says from then
+    // on in the background.
+    fn join(&self) -> Result<(TcpStream, Receiver<ServerMsg>), String> {
+        let mut stream = TcpStream::connect(&self.addr)
+            .map_err(|e| format!("failed to connect to {}: {}", self.addr, e))?;
+        let hello = ClientMsg::Hello {
+            name: self.name.clone(),
+            token: self.token.clone(),
+        };
+        writeln!(stream, "{}", hello).map_err(|e| e.to_string())?;
+
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                // Anything this client doesn't know about is skipped.
+                if let Ok(msg) = line.parse() {
+                    if tx.send(msg).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok((stream, rx))
+    }
+
+    // Keeps trying to get back into the game for a while.
+    fn rejoin(&mut self) -> Result<(TcpStream, Receiver<ServerMsg>), String> {
+        // Only a player in a game keeps a seat to come back to. Anyone else
+        // logs in again and picks up where they were.
+        if !self.playing() {
+            self.token = None;
+            match (self.game, self.side) {
+                (Some(id), Some(Turn::Neither)) => self.lobby = Lobby::Watch(id),
+                (Some(_), Some(side)) => self.lobby = Lobby::Seek(side),
+                _ => {}
+            }
+        }
+        self.note("Lost the connection to the server. Reconnecting...".to_string());
+        self.draw();
+        let give_up = Instant::now() + RETRY_FOR;
+        while Instant::now() < give_up {
+            sleep(RETRY_EVERY);
+            if let Ok(joined) = self.join() {
+                return Ok(joined);
+            }
+        }
+        match &self.token {
+            Some(token) => Err(format!(
+                "lost the connection to {}. Try again with `othello connect {} --name {} --token {}`",
+                self.addr, self.addr, self.name, token
+            )),
+            None => Err(format!("lost the connection to {}", self.addr)),
+        }
+    }
+
+    // Whether we have a seat in a game that has started.
+    fn playing(&self) -> bool {
+        self.board.is_some() && self.side.is_some_and(|side| side != Turn::Neither)
+    }
+
+    fn leave(&self) -> Result<(), String> {
+        if let (Some(token), true) = (&self.token, self.playing()) {
+            println!(
+                "Left the game. Rejoin in time with `othello connect {} --name {} --token {}`.",
+                self.addr, self.name, token
+            );
+        }
+        Ok(())
+    }
+
+    // Takes in what the server said, and returns anything to say back.
+    fn receive(&mut self, msg: ServerMsg) -> Result<Vec<ClientMsg>, String> {
+        let theme = theme::current();
+        match msg {
+            // Back in a game after losing the connection, the server puts
+            // us where we were. Otherwise, go and find one.
+            ServerMsg::Welcome { token, .. } if self.token.is_some() => {
+                self.token = Some(token);
+            }
+            ServerMsg::Welcome { token, .. } => {
+                self.token = Some(token);
+                let msg = match self.lobby {
+                    Lobby::Any => ClientMsg::List,
+                    Lobby::Seek(side) => ClientMsg::Seek(side),
+                    Lobby::Join(id) => ClientMsg::Join(id),
+                    Lobby::Watch(id) => ClientMsg::Watch(id),
+                };
+                return Ok(vec![msg]);
+            }
+            ServerMsg::Open { id, .. } => self.seeks.push(id),
+            ServerMsg::Closed(id) => {
+                self.seeks.retain(|&seek| seek != id);
+                if self.game == Some(id) {
+                    return Err(format!("game {} was taken back", id));
+                }
+            }
+            ServerMsg::Playing { .. } => {}
+            ServerMsg::Listed if self.game.is_none() => {
+                let msg = match self.seeks.first() {
+                    Some(&id) => ClientMsg::Join(id),
+                    None => ClientMsg::Seek(Turn::Black),
+                };
+                return Ok(vec![msg]);
+            }
+            ServerMsg::Listed => {}
+            ServerMsg::Game { id, side } => {
+                self.game = Some(id);
+                self.side = Some(side);
+                let text = match side {
+                    Turn::Neither => format!("Watching game {}.", id),
+                    side => format!("Game {}: you play {}.", id, protocol::side_name(side)),
+                };
+                self.note(text);
+            }
+            ServerMsg::Waiting => self.note("Waiting for an opponent to join...".to_string()),
+            ServerMsg::Players { black, white } => self.names = [black, white],
+            ServerMsg::Board(position) => match Setup::read(&position) {
+                Ok(setup) => {
+                    let mut board = setup.board;
+                    if let Some(before) = &self.board {
+                        board.record_changes(before);
+                    }
+                    self.turn = setup.turn;
+                    self.board = Some(board);
+                }
+                Err(e) => self.note(
+                    theme
+                        .error
+                        .paint(&format!("Bad board from the server: {}.", e))
+                        .to_string(),
+                ),
+            },
+            ServerMsg::Played { side, mv } => {
+                let text = match mv {
+                    Move::Play(_, _) => format!("{} played {}.", self.name_of(side), mv),
+                    _ => format!("{} passed.", self.name_of(side)),
+                };
+                self.note(text);
+            }
+            ServerMsg::Skip(side) => {
+                let text = format!(
+                    "{} has no move, so {} plays again.",
+                    self.name_of(side),
+                    self.name_of(side.opponent())
+                );
+                self.note(theme.info.paint(&text).to_string());
+            }
+            ServerMsg::Turn(side) => self.turn = side,
+            ServerMsg::Moves(_) => {}
+            ServerMsg::Clock { black, white } => self.clock = Some([black, white]),
+            ServerMsg::Away { side, seconds } => {
+                let text = format!(
+                    "{} lost the connection, and has {} seconds to come back.",
+                    self.name_of(side),
+                    seconds
+                );
+                self.note(theme.info.paint(&text).to_string());
+            }
+            ServerMsg::Back(side) => {
+                let text = format!("{} is back.", self.name_of(side));
+                self.note(theme.info.paint(&text).to_string());
+            }
+            ServerMsg::Over(outcome) => {
+                self.outcome = Some(outcome);
+                let text = self.result(outcome);
+                self.note(theme.result.paint(&text).to_string());
+            }
+            ServerMsg::Chat { name, text } => self.note(format!("<{}> {}", name, text)),
+            // Someone took the seek first; look again.
+            ServerMsg::Error(_) if self.game.is_none() && matches!(self.lobby, Lobby::Any) => {
+                self.seeks.clear();
+                return Ok(vec![ClientMsg::List]);
+            }
+            ServerMsg::Error(text) if self.game.is_none() => return Err(text),
+            ServerMsg::Error(text) => {
+                self.note(theme.error.paint(&format!("Server: {}.", text)).to_string());
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn result(&self, outcome: Outcome) -> String {
+        let (winner, loser) = (
+            self.name_of(outcome.winner),
+            self.name_of(outcome.winner.opponent()),
+        );
+        let score = format!("{}-{}", outcome.black, outcome.white);
+        match (outcome.end, outcome.winner) {
+            (End::Finished, Turn::Neither) => format!("Draw, {}.", score),
+            (End::Finished, _) => format!("{} wins, {}.", winner, score),
+            (End::Resigned, _) => format!("{} resigned. {} wins.", loser, winner),
+            (End::Forfeit, _) => format!("{} didn't come back in time. {} wins.", loser, winner),
+            (End::Time, _) => format!("{} ran out of time. {} wins.", loser, winner),
+        }
+    }
+
+    fn name_of(&self, side: Turn) -> &str {
+        match side {
+            Turn::White => &self.names[1],
+            _ => &self.names[0],
+        }
+    }
+
+    fn note(&mut self, line: String) {
+        self.log.push(line);
+    }
+
+    fn draw(&mut self) {
+        let my_turn = self.outcome.is_none() && self.side == Some(self.turn);
+        if let Some(board) = self.board.as_mut() {
+            board.hints = self.hints && my_turn;
+            let turn = if self.outcome.is_some() {
+                Turn::Neither
+            } else {
+                self.turn
+            };
+            board.draw(turn, None, None);
+            let glyphs = theme::glyphs();
+            let time = |i: usize| match self.clock {
+                Some(clock) => format!(" {}", Remaining(Duration::from_millis(clock[i]))),
+                None => String::new(),
+            };
+            println!(
+                "{} {}{}   {} {}{}",
+                glyphs.black_mark,
+                self.names[0],
+                time(0),
+                glyphs.white_mark,
+                self.names[1],
+                time(1)
+            );
+        } else {
+            println!();
+        }
+        let skip = self.log.len().saturating_sub(LOG_LINES);
+        for line in &self.log[skip..] {
+            println!("{}", line);
+        }
+        if self.outcome.is_some() || self.board.is_none() {
+            return;
+        }
+        if my_turn {
+            let example = config::current().notation.format(4, 3);
+            println!("Your move. (Example: {})", example);
+            println!("Enter `p` to pass or `r` to resign.");
+            println!("Enter `h` to show or hide legal moves, or `q` to leave.");
+        } else if self.side == Some(Turn::Neither) {
+            println!(
+                "{} to move. Enter `q` to stop watching.",
+                self.name_of(self.turn)
+            );
+        } else {
+            let other = self.name_of(self.turn);
+            println!("Waiting for {}... Enter `q` to leave.", other);
+        }
+        println!("Enter `say <text>` to chat.");
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::events::Turn;
+use crate::theme;
+
+#[derive(Clone, Copy)]
+pub enum TimeControl {
+    // Base time, plus a fixed increment added after every move.
+    Fischer { base: Duration, increment: Duration },
+    // Base time, but up to `delay` of the time spent on a move is given back.
+    Bronstein { base: Duration, delay: Duration },
+}
+
+// Parses `<minutes>+<seconds>` as Fischer and `<minutes>d<seconds>`
+// as Bronstein, e.g. `5+3` or `10d5`.
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sep, base, extra) = match (s.find('+'), s.find('d')) {
+            (Some(i), None) => ('+', &s[..i], &s[i + 1..]),
+            (None, Some(i)) => ('d', &s[..i], &s[i + 1..]),
+            _ => return Err(format!("invalid time control `{}`", s)),
+        };
+        let minutes = base
+            .parse::<f64>()
+            .map_err(|_| format!("invalid base time `{}`", base))?;
+        let seconds = extra
+            .parse::<f64>()
+            .map_err(|_| format!("invalid increment or delay `{}`", extra))?;
+        if minutes <= 0.0 || seconds < 0.0 || !minutes.is_finite() || !seconds.is_finite() {
+            return Err(format!("invalid time control `{}`", s));
+        }
+
+        let base = Duration::from_secs_f64(minutes * 60.0);
+        let extra = Duration::from_secs_f64(seconds);
+        Ok(match sep {
+            '+' => TimeControl::Fischer {
+                base,
+                increment: extra,
+            },
+            _ => TimeControl::Bronstein { base, delay: extra },
+        })
+    }
+}
+
+pub struct Clock {
+    control: TimeControl,
+    black: Duration,
+    white: Duration,
+    running: Option<(Turn, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let base = match control {
+            TimeControl::Fischer { base, .. } | TimeControl::Bronstein { base, .. } => base,
+        };
+        Self {
+            control,
+            black: base,
+            white: base,
+            running: None,
+        }
+    }
+
+    // Time left for `turn`, counting down live while their clock runs.
+    pub fn remaining(&self, turn: Turn) -> Duration {
+        let remaining = match turn {
+            Turn::Black => self.black,
+            Turn::White => self.white,
+            Turn::Neither => Duration::from_secs(0),
+        };
+        match self.running {
+            Some((running, started)) if running == turn => {
+                remaining.checked_sub(started.elapsed()).unwrap_or_default()
+            }
+            _ => remaining,
+        }
+    }
+
+    // The time left for one player, formatted like the full clock.
+    pub fn show(&self, turn: Turn) -> String {
+        Remaining(self.remaining(turn)).to_string()
+    }
+
+    // Starts the clock of whoever is about to move, and returns the moment
+    // their flag falls.
+    pub fn start(&mut self, turn: Turn) -> Instant {
+        let now = Instant::now();
+        self.running = Some((turn, now));
+        now + self.remaining(turn)
+    }
+
+    // Stops the running clock and charges the elapsed time to `turn`.
+    // The increment or delay is only credited if they `completed` a move.
+    // Returns `false` if their flag fell in the meantime.
+    pub fn stop(&mut self, turn: Turn, completed: bool) -> bool {
+        let elapsed = match self.running.take() {
+            Some((_, started)) => started.elapsed(),
+            None => return true,
+        };
+        let control = self.control;
+        let remaining = match turn {
+            Turn::Black => &mut self.black,
+            Turn::White => &mut self.white,
+            Turn::Neither => return true,
+        };
+
+        if elapsed >= *remaining {
+            *remaining = Duration::from_secs(0);
+            return false;
+        }
+        *remaining -= elapsed;
+        if !completed {
+            return true;
+        }
+        *remaining += match control {
+            TimeControl::Fischer { increment, .. } => increment,
+            TimeControl::Bronstein { delay, .. } => elapsed.min(delay),
+        };
+        true
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let glyphs = theme::glyphs();
+        write!(
+            f,
+            "{} {}   {} {}",
+            glyphs.black_mark,
+            Remaining(self.remaining(Turn::Black)),
+            glyphs.white_mark,
+            Remaining(self.remaining(Turn::White))
+        )
+    }
+}
+
+// `m:ss`, with tenths of a second once a player is under ten seconds.
+pub struct Remaining(pub Duration);
+
+impl fmt::Display for Remaining {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        if secs < 10 {
+            write!(f, "0:0{}.{}", secs, self.0.subsec_millis() / 100)
+        } else {
+            write!(f, "{}:{:02}", secs / 60, secs % 60)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A clock for `turn` that has been running for `secs` seconds.
+    fn spent(control: &str, turn: Turn, secs: u64) -> Clock {
+        let mut clock = Clock::new(control.parse().unwrap());
+        let started = Instant::now() - Duration::from_secs(secs);
+        clock.running = Some((turn, started));
+        clock
+    }
+
+    fn secs(duration: Duration) -> f64 {
+        duration.as_secs_f64()
+    }
+
+    #[test]
+    fn parses_fischer_and_bronstein() {
+        match "5+3".parse().unwrap() {
+            TimeControl::Fischer { base, increment } => {
+                assert_eq!(base, Duration::from_secs(300));
+                assert_eq!(increment, Duration::from_secs(3));
+            }
+            _ => panic!("5+3 is Fischer"),
+        }
+        match "0.5d2".parse().unwrap() {
+            TimeControl::Bronstein { base, delay } => {
+                assert_eq!(base, Duration::from_secs(30));
+                assert_eq!(delay, Duration::from_secs(2));
+            }
+            _ => panic!("0.5d2 is Bronstein"),
+        }
+        for bad in ["5", "5+", "+3", "x+3", "0+3", "5+-1", "5+3d2", "inf+1"] {
+            assert!(bad.parse::<TimeControl>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn credits_the_fischer_increment_after_a_move() {
+        let mut clock = spent("1+2", Turn::Black, 1);
+        assert!(clock.stop(Turn::Black, true));
+        let left = secs(clock.remaining(Turn::Black));
+        assert!(left > 60.9 && left <= 61.0, "{}", left);
+        assert_eq!(clock.remaining(Turn::White), Duration::from_secs(60));
+
+        // Nothing is credited for what isn't a move.
+        let mut clock = spent("1+2", Turn::White, 1);
+        assert!(clock.stop(Turn::White, false));
+        assert!(secs(clock.remaining(Turn::White)) <= 59.0);
+    }
+
+    #[test]
+    fn gives_back_no_more_of_the_bronstein_delay_than_was_used() {
+        let mut clock = spent("1d3", Turn::Black, 1);
+        assert!(clock.stop(Turn::Black, true));
+        assert_eq!(clock.remaining(Turn::Black), Duration::from_secs(60));
+
+        let mut clock = spent("1d3", Turn::Black, 5);
+        assert!(clock.stop(Turn::Black, true));
+        let left = secs(clock.remaining(Turn::Black));
+        assert!(left > 57.9 && left <= 58.0, "{}", left);
+    }
+
+    #[test]
+    fn stops_false_once_the_flag_has_fallen() {
+        let mut clock = spent("1+5", Turn::White, 61);
+        assert_eq!(clock.remaining(Turn::White), Duration::from_secs(0));
+        assert!(!clock.stop(Turn::White, true));
+        assert_eq!(clock.remaining(Turn::White), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+
+use crate::archive;
+use crate::board::Board;
+use crate::config;
+use crate::engine::{self, Engine, MAX_SOLVE_EMPTIES};
+use crate::events::{InputHandler, Move, Notation, Turn};
+use crate::game::Game;
+use crate::profiles::{self, Profiles};
+use crate::protocol::{End, Outcome};
+use crate::setup::{self, Setup};
+use crate::theme;
+use crate::transcript::{self, Position};
+use crate::tui;
+use crate::viewer;
+
+// Reads a whole file, or stdin if there is no file or it is `-`.
+pub fn read_input(file: Option<&Path>) -> Result<String, String> {
+    let mut text = String::new();
+    match file {
+        Some(path) if path != Path::new("-") => {
+            text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        }
+        _ => {
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("failed to read stdin: {}", e))?;
+        }
+    }
+    Ok(text)
+}
+
+// The position after the moves of a transcript, which must not be over yet.
+fn position_after(moves: &str) -> Result<Position, String> {
+    let moves = transcript::parse(moves, config::current().notation)?;
+    let position = transcript::replay(&moves)?
+        .pop()
+        .expect("starting position");
+    if position.is_over() {
+        return Err("the game is already over".to_string());
+    }
+    Ok(position)
+}
+
+// `othello replay`: prints every position of a game, one after the other,
+// or steps through them in the full-screen viewer when `interactive`.
+pub fn replay(text: &str, interactive: bool) -> Result<(), String> {
+    let config = config::current();
+    let game = transcript::read_game(text, config.notation)?;
+    // A game that started from a position of its own begins with it, and
+    // so does any game played on from it.
+    let start = match game.start {
+        Some(start) => start,
+        None => Setup::new(&config.start, &config.holes, config.size)?,
+    };
+    start.fits(config.size)?;
+    setup::init(start);
+    let positions = transcript::replay(&game.moves)?;
+    if !interactive {
+        for (i, position) in positions.iter().enumerate() {
+            match position.played {
+                Some(played) => println!("\n{}. {}", i, played),
+                None => println!("Start"),
+            }
+            position.board.print(position.turn, position.mv, None);
+        }
+        return Ok(());
+    }
+
+    let names = viewer::names(game.black, game.white);
+    let ply = match viewer::view(&positions, names).map_err(|e| format!("terminal error: {}", e))? {
+        Some(ply) => ply,
+        None => return Ok(()),
+    };
+    let moves = viewer::moves_to(&positions, ply);
+    if config.tui {
+        tui::start(config.clock, &moves).map_err(|e| format!("terminal error: {}", e))
+    } else {
+        Game::start(config.clock, &moves)
+    }
+}
+
+// `othello analyze`: scores every legal move of a position.
+pub fn analyze(moves: &str, depth: u32) -> Result<(), String> {
+    let position = position_after(moves)?;
+    let start = Instant::now();
+    let mut engine = Engine::new(None);
+    let scored = engine.analyze(&position.board, position.turn, depth);
+
+    println!(
+        "{} {} to move, depth {}",
+        theme::glyphs().mark(position.turn),
+        config::current().name(position.turn),
+        depth
+    );
+    for (square, score) in scored {
+        println!(
+            "  {:<4}{:+}",
+            Move::Play(square.0, square.1).to_string(),
+            score
+        );
+    }
+    println!(
+        "{} nodes in {:.3}s",
+        engine.nodes,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+// `othello solve`: the exact result of a position with best play.
+pub fn solve(moves: &str) -> Result<(), String> {
+    let position = position_after(moves)?;
+    let empties = engine::empties(&position.board);
+    if empties > MAX_SOLVE_EMPTIES {
+        return Err(format!(
+            "{} empty squares is too many to solve (at most {})",
+            empties, MAX_SOLVE_EMPTIES
+        ));
+    }
+
+    let start = Instant::now();
+    let mut engine = Engine::new(None);
+    let (best, score) = engine.solve(&position.board, position.turn);
+    let best = best.map_or(Move::Pass, |(row, col)| Move::Play(row, col));
+    println!(
+        "{} {} to move: {} ({:+})",
+        theme::glyphs().mark(position.turn),
+        config::current().name(position.turn),
+        best,
+        score
+    );
+    println!(
+        "{} nodes in {:.3}s",
+        engine.nodes,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+// `othello convert`: rewrites a transcript from one notation into another.
+pub fn convert(text: &str, from: Notation, to: Notation) -> Result<String, String> {
+    let (start, text) = setup::split(text)?;
+    let moves = transcript::parse(text, from)?;
+    // Only legal games are worth converting.
+    match &start {
+        Some(start) => transcript::replay_from(start, &moves)?,
+        None => transcript::replay(&moves)?,
+    };
+    let moves = transcript::format(&moves, to);
+    Ok(match start {
+        Some(start) => format!("{}\n{}", start, moves),
+        None => moves,
+    })
+}
+
+// `othello match`: plays AI players against each other.
+//
+// Every opening is played twice, with the players swapping colors, so
+// neither of them gets the better side of it.
+pub fn play_match(players: [(String, u8); 2], games: u32, opening: usize) -> Result<(), String> {
+    let mut wins = [0; 2];
+    let mut draws = 0;
+    // Games between two different levels count towards their ratings.
+    let keys = [profiles::ai(players[0].1), profiles::ai(players[1].1)];
+    let path = profiles::path().filter(|_| keys[0] != keys[1]);
+    let mut rated = match &path {
+        Some(path) => Some(Profiles::load(path)?),
+        None => None,
+    };
+    let mut start = (setup::start().board.clone(), setup::start().turn);
+    let mut opened = Vec::new();
+    for game in 0..games {
+        if game % 2 == 0 {
+            let (board, turn, squares) = random_opening(opening);
+            opened = squares.iter().map(|&(r, c)| Move::Play(r, c)).collect();
+            if !opened.is_empty() {
+                let squares = opened.iter().map(Move::to_string);
+                println!("Opening: {}", squares.collect::<Vec<_>>().join(" "));
+            }
+            start = (board, turn);
+        }
+
+        // Whoever of the two is playing black this game.
+        let first = game as usize % 2;
+        let (black, white) = (&players[first], &players[1 - first]);
+        let (b, w, moves) = play_out(start.0.clone(), start.1, [black.1, white.1]);
+        println!("Game {}: {} {} - {} {}", game + 1, black.0, b, w, white.0);
+        let winner = config::current().variant.winner(b, w);
+        match winner {
+            Turn::Black => wins[first] += 1,
+            Turn::White => wins[1 - first] += 1,
+            Turn::Neither => draws += 1,
+        }
+        let outcome = Outcome {
+            end: End::Finished,
+            winner,
+            black: b,
+            white: w,
+        };
+        if let Some(profiles) = rated.as_mut() {
+            profiles.record(&keys[first], &keys[1 - first], &outcome);
+        }
+        let moves = [opened.as_slice(), &moves].concat();
+        archive::store(&keys[first], &keys[1 - first], &outcome, &moves)?;
+    }
+
+    println!(
+        "\n{} wins {}, {} wins {}, {} drawn",
+        players[0].0, wins[0], players[1].0, wins[1], draws
+    );
+    if let (Some(profiles), Some(path)) = (rated, path) {
+        profiles.save(&path)?;
+        println!(
+            "Ratings: {} {:.0}, {} {:.0}",
+            keys[0],
+            profiles.rating(&keys[0]),
+            keys[1],
+            profiles.rating(&keys[1])
+        );
+    }
+    Ok(())
+}
+
+// A few random moves from the start, stopping short of the end of the game.
+fn random_opening(plies: usize) -> (Board, Turn, Vec<(usize, usize)>) {
+    let mut rng = rand::thread_rng();
+    let start = setup::start();
+    let mut board = start.board.clone();
+    let mut turn = start.turn;
+    let mut squares = Vec::new();
+    for _ in 0..plies {
+        let mut next = board.clone();
+        let (row, col) = match Move::legal_moves(&board, turn).choose(&mut rng) {
+            Some(&square) => square,
+            None => break,
+        };
+        Move::is_valid_move(&mut next, turn, row, col);
+        match Move::apply_move(&mut next, turn, Move::Play(row, col)) {
+            (_, Some(Move::Win(_, _))) => break,
+            (next_turn, _) => turn = next_turn,
+        }
+        board = next;
+        squares.push((row, col));
+    }
+    (board, turn, squares)
+}
+
+// Plays a game to the end between AIs of the given `[black, white]` levels,
+// and returns the final disc counts and the moves played.
+fn play_out(mut board: Board, mut turn: Turn, levels: [u8; 2]) -> (u16, u16, Vec<Move>) {
+    let mut moves = Vec::new();
+    loop {
+        let level = if turn == Turn::Black {
+            levels[0]
+        } else {
+            levels[1]
+        };
+        let (row, col) = match engine::pick(&board, turn, level, None) {
+            Move::Play(row, col) => (row, col),
+            // It is never the turn of a player who can't move.
+            _ => {
+                let (b, w) = board.count_discs();
+                return (b, w, moves);
+            }
+        };
+        Move::is_valid_move(&mut board, turn, row, col);
+        moves.push(Move::Play(row, col));
+        match Move::apply_move(&mut board, turn, Move::Play(row, col)) {
+            (_, Some(Move::Win(b, w))) => return (b, w, moves),
+            (next, _) => turn = next,
+        }
+    }
+}
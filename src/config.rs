@@ -0,0 +1,373 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::board::{self, Variant};
+use crate::clock::TimeControl;
+use crate::engine::MAX_LEVEL;
+use crate::events::{Notation, Turn};
+use crate::setup::{Holes, Start};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Settings for a run, layered as built-in defaults, then the config file,
+// then command-line flags.
+//
+// The config file lives at `~/.config/othello/config.toml` (or under
+// `$XDG_CONFIG_HOME`), and every key in it is optional:
+//
+//     theme = "light"        # any built-in or user theme
+//     ascii = false          # draw the board with plain ASCII
+//     tui = false            # play in the full-screen terminal UI
+//     hints = true           # show legal moves
+//     notation = "classic"   # `classic` (3d) or `standard` (d3)
+//     size = 8               # squares across, even and 4 to 16
+//     variant = "othello"    # or "anti", where the fewest discs win
+//     start = "standard"     # "random", or a position string like `othello play --setup` shows
+//     holes = "none"         # "random", or squares to block like "c3 f6"
+//     clock = "5+3"          # a time control, or "none"
+//     ai_level = 3           # 1 to 10, for AI players without a level of their own
+//
+//     [players]
+//     black = "Alice"                         # a human player
+//     white = { name = "Computer", ai = 5 }   # an AI player
+pub struct Config {
+    pub black: Player,
+    pub white: Player,
+    pub ai_level: u8,
+    pub theme: Option<String>,
+    pub ascii: bool,
+    pub tui: bool,
+    pub hints: bool,
+    pub notation: Notation,
+    pub size: usize,
+    pub variant: Variant,
+    pub start: Start,
+    pub holes: Holes,
+    pub clock: Option<TimeControl>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            black: Player::human("Black"),
+            white: Player::human("White"),
+            ai_level: 3,
+            theme: None,
+            ascii: false,
+            tui: false,
+            hints: true,
+            notation: Notation::Classic,
+            size: 8,
+            variant: Variant::Othello,
+            start: Start::Standard,
+            holes: Holes::None,
+            clock: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    theme: Option<String>,
+    ascii: Option<bool>,
+    tui: Option<bool>,
+    hints: Option<bool>,
+    notation: Option<Notation>,
+    size: Option<usize>,
+    variant: Option<Variant>,
+    start: Option<String>,
+    holes: Option<String>,
+    clock: Option<String>,
+    ai_level: Option<u8>,
+    players: Option<PlayersFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlayersFile {
+    black: Option<PlayerFile>,
+    white: Option<PlayerFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PlayerFile {
+    Name(String),
+    Full(PlayerTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlayerTable {
+    name: Option<String>,
+    ai: Option<u8>,
+}
+
+#[derive(Clone)]
+pub struct Player {
+    pub name: String,
+    // The AI level, or `None` for a human at the keyboard.
+    pub ai: Option<u8>,
+}
+
+impl Player {
+    fn human(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ai: None,
+        }
+    }
+
+    fn apply(&mut self, file: PlayerFile) -> Result<(), String> {
+        match file {
+            PlayerFile::Name(name) => self.name = name,
+            PlayerFile::Full(PlayerTable { name, ai }) => {
+                if let Some(name) = name {
+                    self.name = name;
+                }
+                if let Some(level) = ai {
+                    self.ai = Some(check_level(level)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Reads `human`, `ai` or `ai:<level>` into an AI level, or `None` for a
+// human. A bare `ai` plays at `default_level`.
+pub fn parse_player(spec: &str, default_level: u8) -> Result<Option<u8>, String> {
+    match spec {
+        "human" => Ok(None),
+        "ai" => Ok(Some(default_level)),
+        _ => match spec.strip_prefix("ai:").map(str::parse::<u8>) {
+            Some(Ok(level)) => check_level(level).map(Some),
+            _ => Err(format!(
+                "invalid player `{}` (expected `human`, `ai` or `ai:<level>`)",
+                spec
+            )),
+        },
+    }
+}
+
+pub fn check_level(level: u8) -> Result<u8, String> {
+    if (1..=MAX_LEVEL).contains(&level) {
+        Ok(level)
+    } else {
+        Err(format!(
+            "AI level must be 1 to {}, not {}",
+            MAX_LEVEL, level
+        ))
+    }
+}
+
+impl Config {
+    // Defaults overlaid with the config file at `path`, or the usual one.
+    // A missing file is only an error if it was asked for explicitly.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match dir() {
+                Some(dir) => (dir.join("config.toml"), false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let file: ConfigFile =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut config = Config::default();
+        config
+            .apply(file)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    fn apply(&mut self, file: ConfigFile) -> Result<(), String> {
+        if let Some(level) = file.ai_level {
+            self.ai_level = check_level(level).map_err(|e| format!("ai_level: {}", e))?;
+        }
+        if let Some(players) = file.players {
+            if let Some(black) = players.black {
+                self.black
+                    .apply(black)
+                    .map_err(|e| format!("players.black: {}", e))?;
+            }
+            if let Some(white) = players.white {
+                self.white
+                    .apply(white)
+                    .map_err(|e| format!("players.white: {}", e))?;
+            }
+        }
+        if file.theme.is_some() {
+            self.theme = file.theme;
+        }
+        self.ascii = file.ascii.unwrap_or(self.ascii);
+        self.tui = file.tui.unwrap_or(self.tui);
+        self.hints = file.hints.unwrap_or(self.hints);
+        self.notation = file.notation.unwrap_or(self.notation);
+        self.variant = file.variant.unwrap_or(self.variant);
+        if let Some(start) = file.start {
+            self.start = start.parse().map_err(|e| format!("start: {}", e))?;
+        }
+        if let Some(holes) = file.holes {
+            self.holes = holes.parse().map_err(|e| format!("holes: {}", e))?;
+        }
+        if let Some(size) = file.size {
+            self.size = board::check_size(size).map_err(|e| format!("size: {}", e))?;
+        }
+        match file.clock.as_deref() {
+            None => {}
+            Some("none") => self.clock = None,
+            Some(clock) => self.clock = Some(clock.parse().map_err(|e| format!("clock: {}", e))?),
+        }
+        Ok(())
+    }
+
+    // Sets who plays `turn` from `human`, `ai` or `ai:<level>`.
+    pub fn set_player(&mut self, turn: Turn, spec: &str) -> Result<(), String> {
+        let ai = parse_player(spec, self.ai_level)?;
+        match turn {
+            Turn::Black => self.black.ai = ai,
+            Turn::White => self.white.ai = ai,
+            Turn::Neither => {}
+        }
+        Ok(())
+    }
+
+    pub fn player(&self, turn: Turn) -> &Player {
+        match turn {
+            Turn::White => &self.white,
+            _ => &self.black,
+        }
+    }
+
+    pub fn name(&self, turn: Turn) -> &str {
+        match turn {
+            Turn::Neither => "",
+            _ => &self.player(turn).name,
+        }
+    }
+}
+
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn current() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("othello")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("othello")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(config: &mut Config, text: &str) -> Result<(), String> {
+        config.apply(toml::from_str(text).map_err(|e| e.to_string())?)
+    }
+
+    #[test]
+    fn files_only_change_the_keys_they_set() {
+        let mut config = Config::default();
+        layer(
+            &mut config,
+            "theme = \"light\"\n\
+             size = 6\n\
+             notation = \"standard\"\n\
+             clock = \"5+3\"\n\
+             ai_level = 7\n\
+             [players]\n\
+             black = \"Alice\"\n\
+             white = { name = \"Computer\", ai = 5 }\n",
+        )
+        .unwrap();
+        assert_eq!(config.theme.as_deref(), Some("light"));
+        assert_eq!(config.size, 6);
+        assert!(config.notation == Notation::Standard);
+        assert!(config.clock.is_some());
+        assert_eq!(config.ai_level, 7);
+        assert_eq!(config.black.name, "Alice");
+        assert_eq!(config.black.ai, None);
+        assert_eq!(config.white.name, "Computer");
+        assert_eq!(config.white.ai, Some(5));
+        assert!(config.hints);
+        assert!(!config.ascii);
+        assert!(config.variant == Variant::Othello);
+
+        // A later layer keeps whatever it leaves out.
+        layer(
+            &mut config,
+            "hints = false\nclock = \"none\"\n[players]\nwhite = { name = \"Edax\" }\n",
+        )
+        .unwrap();
+        assert!(!config.hints);
+        assert!(config.clock.is_none());
+        assert_eq!(config.size, 6);
+        assert_eq!(config.theme.as_deref(), Some("light"));
+        assert_eq!(config.white.name, "Edax");
+        assert_eq!(config.white.ai, Some(5));
+    }
+
+    #[test]
+    fn flags_go_over_the_file() {
+        let mut config = Config::default();
+        layer(&mut config, "ai_level = 7\n[players]\nblack = { ai = 2 }\n").unwrap();
+        config.set_player(Turn::Black, "human").unwrap();
+        config.set_player(Turn::White, "ai").unwrap();
+        assert_eq!(config.black.ai, None);
+        assert_eq!(config.white.ai, Some(7));
+        config.set_player(Turn::White, "ai:4").unwrap();
+        assert_eq!(config.white.ai, Some(4));
+        assert!(config.set_player(Turn::White, "ai:11").is_err());
+        assert!(config.set_player(Turn::White, "robot").is_err());
+    }
+
+    #[test]
+    fn names_the_key_that_is_wrong() {
+        let err = |text| layer(&mut Config::default(), text).unwrap_err();
+        assert!(err("ai_level = 11\n").starts_with("ai_level: "));
+        assert!(err("size = 5\n").starts_with("size: "));
+        assert!(err("clock = \"soon\"\n").starts_with("clock: "));
+        assert!(err("[players]\nwhite = { ai = 0 }\n").starts_with("players.white: "));
+        assert!(err("colour = true\n").contains("colour"));
+    }
+
+    #[test]
+    fn loads_files_that_were_asked_for() {
+        let dir = std::env::temp_dir().join(format!("othello-config-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        let err = Config::load(Some(&path)).err().unwrap();
+        assert!(err.starts_with(&path.display().to_string()), "{}", err);
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "size = 10\nvariant = \"anti\"\n").unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.size, 10);
+        assert!(config.variant == Variant::Anti);
+        assert!(config.notation == Notation::Classic);
+
+        fs::write(&path, "size = 7\n").unwrap();
+        let err = Config::load(Some(&path)).err().unwrap();
+        assert!(err.contains("size: "), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
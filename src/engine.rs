@@ -0,0 +1,450 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::board::{Board, Cell, Variant};
+use crate::clock::Clock;
+use crate::config;
+use crate::events::{Move, Turn};
+
+pub const MAX_LEVEL: u8 = 10;
+
+// Beyond this many empty squares an exact solve takes too long to wait for.
+pub const MAX_SOLVE_EMPTIES: usize = 16;
+
+// The classic square weights: corners are worth a lot, and the squares
+// that give corners away are worth less than nothing. On an 8x8 board:
+//
+//     100 -20  10   5   5  10 -20 100
+//     -20 -50  -2  -2  -2  -2 -50 -20
+//      10  -2  -1  -1  -1  -1  -2  10
+//       5  -2  -1  -1  -1  -1  -2   5
+//
+// and so on, mirrored. Other sizes keep the same shape around the edges.
+fn weight(size: usize, row: usize, col: usize) -> i32 {
+    let from_edge = |i: usize| (i - 1).min(size - i);
+    let (near, far) = {
+        let (r, c) = (from_edge(row), from_edge(col));
+        (r.min(c), r.max(c))
+    };
+    match (near, far) {
+        (0, 0) => 100,
+        (0, 1) => -20,
+        (1, 1) => -50,
+        (0, 2) => 10,
+        (0, _) => 5,
+        (1, _) => -2,
+        _ => -1,
+    }
+}
+
+// Scores are in discs, from the point of view of the side to move.
+// They are exact once the search reaches the end of the game, and an
+// estimate from square weights and mobility before that.
+const INFINITY: i32 = 10_000;
+
+// What one search found out about a position, for the next to reuse.
+#[derive(Clone, Copy)]
+struct Entry {
+    key: u64,
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best: Option<(u8, u8)>,
+}
+
+// Whether a stored score is exact, or only as good as the search needed
+// to know before cutting off.
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// A transposition table, which searches on any number of threads can
+// share. Every slot has a lock of its own, so searches only wait on each
+// other when they want the same slot at the same time.
+pub struct Transpositions {
+    slots: Vec<Mutex<Option<Entry>>>,
+}
+
+impl Transpositions {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            slots: (0..slots.max(1)).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Mutex<Option<Entry>> {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    fn probe(&self, key: u64) -> Option<Entry> {
+        // A search that panicked can't have left an entry half written.
+        let slot = self.slot(key).lock().unwrap_or_else(|e| e.into_inner());
+        slot.filter(|entry| entry.key == key)
+    }
+
+    // Keeps the deeper of two searches of the same position, and the
+    // newer of two different ones.
+    fn store(&self, entry: Entry) {
+        let mut slot = self
+            .slot(entry.key)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match *slot {
+            Some(old) if old.key == entry.key && old.depth > entry.depth => {}
+            _ => *slot = Some(entry),
+        }
+    }
+
+    // The best move stored for a position, if it is still legal there.
+    fn best(&self, board: &Board, turn: Turn, variant: Variant) -> Option<(usize, usize)> {
+        let (row, col) = self.probe(key(board, turn, variant))?.best?;
+        let (row, col) = (row as usize, col as usize);
+        Move::after(board, turn, row, col).map(|_| (row, col))
+    }
+}
+
+// Tells positions apart by their discs and holes and who is to move.
+fn key(board: &Board, turn: Turn, variant: Variant) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (board.size, turn as u8, variant as u8).hash(&mut hasher);
+    for row in &board.cells[1..] {
+        for cell in &row[1..] {
+            let code: u8 = match cell {
+                Cell::Black => 1,
+                Cell::White => 2,
+                Cell::Blocked => 3,
+                _ => 0,
+            };
+            code.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+pub struct Engine {
+    pub nodes: u64,
+    // What the search is playing for, which is the fewest discs in `Anti`.
+    pub variant: Variant,
+    // How deep the last `analyze` got before running out of time.
+    pub depth: u32,
+    deadline: Option<Instant>,
+    aborted: bool,
+    table: Option<Arc<Transpositions>>,
+}
+
+impl Engine {
+    // A search that gives up once `deadline` passes.
+    pub fn new(deadline: Option<Instant>) -> Self {
+        Self {
+            nodes: 0,
+            variant: config::current().variant,
+            depth: 0,
+            deadline,
+            aborted: false,
+            table: None,
+        }
+    }
+
+    // The same, remembering what it finds in `table` and reusing what
+    // other searches put there.
+    pub fn with_table(deadline: Option<Instant>, table: Arc<Transpositions>) -> Self {
+        Self {
+            table: Some(table),
+            ..Self::new(deadline)
+        }
+    }
+
+    // Whether the search ran out of time, which leaves its results short
+    // of the depth asked for.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    // How deep an AI of `level` searches, and how many empties it solves exactly.
+    pub fn depths(level: u8) -> (u32, usize) {
+        let level = level.clamp(1, MAX_LEVEL);
+        (level as u32, (level as usize * 2).min(MAX_SOLVE_EMPTIES))
+    }
+
+    // Picks a move for an AI of `level`.
+    pub fn choose(&mut self, board: &Board, turn: Turn, level: u8) -> Option<(usize, usize)> {
+        let (depth, solve) = Self::depths(level);
+        let depth = if empties(board) <= solve {
+            empties(board) as u32
+        } else {
+            depth
+        };
+        self.analyze(board, turn, depth)
+            .first()
+            .map(|&(square, _)| square)
+    }
+
+    // Scores every legal move for `turn`, best first.
+    //
+    // Searches one ply deeper at a time, so that running out of time
+    // still leaves the results of the last depth that finished.
+    pub fn analyze(&mut self, board: &Board, turn: Turn, depth: u32) -> Vec<((usize, usize), i32)> {
+        let mut scored = Move::legal_moves(board, turn)
+            .into_iter()
+            .map(|square| (square, 0))
+            .collect::<Vec<_>>();
+
+        for d in 1..=depth.max(1) {
+            let mut next = Vec::with_capacity(scored.len());
+            for &(square, _) in &scored {
+                let after = Move::after(board, turn, square.0, square.1).expect("legal move");
+                let score = -self.negamax(&after, turn.opponent(), d - 1, -INFINITY, INFINITY);
+                if self.aborted {
+                    break;
+                }
+                next.push((square, score));
+            }
+            if self.aborted {
+                break;
+            }
+            // Stable, so ties keep the order of the previous depth.
+            next.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+            scored = next;
+            self.depth = d;
+        }
+        scored
+    }
+
+    // The line of play the table expects after `first`, up to `plies`
+    // moves long in all, passes included. Without a table it is just `first`.
+    pub fn pv(&self, board: &Board, turn: Turn, first: (usize, usize), plies: usize) -> Vec<Move> {
+        let mut line = vec![Move::Play(first.0, first.1)];
+        let table = match &self.table {
+            Some(table) => table,
+            None => return line,
+        };
+        let mut board = Move::after(board, turn, first.0, first.1).expect("legal move");
+        let mut turn = turn.opponent();
+        while line.len() < plies {
+            if !Move::has_moves(&board, turn) {
+                if !Move::has_moves(&board, turn.opponent()) {
+                    break;
+                }
+                line.push(Move::Pass);
+                turn = turn.opponent();
+                continue;
+            }
+            let (row, col) = match table.best(&board, turn, self.variant) {
+                Some(best) => best,
+                None => break,
+            };
+            line.push(Move::Play(row, col));
+            board = Move::after(&board, turn, row, col).expect("legal move");
+            turn = turn.opponent();
+        }
+        line
+    }
+
+    // The exact final disc difference with best play, and the move that gets it.
+    pub fn solve(&mut self, board: &Board, turn: Turn) -> (Option<(usize, usize)>, i32) {
+        let depth = empties(board) as u32;
+        match self.analyze(board, turn, depth).first() {
+            Some(&(square, score)) => (Some(square), score),
+            // Nothing to play here, so either pass or the game is over.
+            None if Move::has_moves(board, turn.opponent()) => (
+                None,
+                -self.negamax(board, turn.opponent(), depth, -INFINITY, INFINITY),
+            ),
+            None => (None, final_score(board, turn, self.variant)),
+        }
+    }
+
+    fn negamax(
+        &mut self,
+        board: &Board,
+        turn: Turn,
+        depth: u32,
+        mut alpha: i32,
+        mut beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024) && self.deadline.is_some_and(|d| Instant::now() >= d) {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        // What an earlier search of this position can tell us: maybe the
+        // score outright, and at least which move to try first.
+        let key = self.table.as_ref().map(|_| key(board, turn, self.variant));
+        let stored = match (&self.table, key) {
+            (Some(table), Some(key)) => table.probe(key),
+            _ => None,
+        };
+        let original_alpha = alpha;
+        if let Some(entry) = stored.filter(|entry| entry.depth >= depth) {
+            match entry.bound {
+                Bound::Exact => return entry.score,
+                Bound::Lower => alpha = alpha.max(entry.score),
+                Bound::Upper => beta = beta.min(entry.score),
+            }
+            if alpha >= beta {
+                return entry.score;
+            }
+        }
+
+        let mut moves = Move::legal_moves(board, turn);
+        if moves.is_empty() {
+            // Passing doesn't use up depth, so a search as deep as the
+            // number of empties always reaches the end of the game.
+            if Move::has_moves(board, turn.opponent()) {
+                return -self.negamax(board, turn.opponent(), depth, -beta, -alpha);
+            }
+            return final_score(board, turn, self.variant);
+        }
+        if depth == 0 {
+            return evaluate(board, turn, moves.len(), self.variant);
+        }
+
+        if let Some((row, col)) = stored.and_then(|entry| entry.best) {
+            let first = (row as usize, col as usize);
+            if let Some(i) = moves.iter().position(|&square| square == first) {
+                moves[..=i].rotate_right(1);
+            }
+        }
+
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for (row, col) in moves {
+            let after = Move::after(board, turn, row, col).expect("legal move");
+            let score = -self.negamax(&after, turn.opponent(), depth - 1, -beta, -alpha);
+            if score > best {
+                best = score;
+                best_move = Some((row as u8, col as u8));
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if let (Some(table), Some(key), false) = (&self.table, key, self.aborted) {
+            let bound = if best <= original_alpha {
+                Bound::Upper
+            } else if best >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            table.store(Entry {
+                key,
+                depth,
+                score: best,
+                bound,
+                best: best_move,
+            });
+        }
+        best
+    }
+}
+
+// The move an AI of `level` makes for `turn`. With a clock running, it
+// spends an even share of what is left on each of its remaining moves.
+pub fn pick(board: &Board, turn: Turn, level: u8, clock: Option<&Clock>) -> Move {
+    let deadline = clock.map(|clock| {
+        let moves_left = (empties(board) as u32).div_ceil(2) + 1;
+        Instant::now() + clock.remaining(turn) / moves_left
+    });
+    match Engine::new(deadline).choose(board, turn, level) {
+        Some((row, col)) => Move::Play(row, col),
+        None => Move::Pass,
+    }
+}
+
+pub fn empties(board: &Board) -> usize {
+    board.empties()
+}
+
+// Disc difference once neither side can move.
+// In `Anti`, every disc short of the other player is one to the good.
+fn final_score(board: &Board, turn: Turn, variant: Variant) -> i32 {
+    let (black, white) = board.count_discs();
+    let diff = black as i32 - white as i32;
+    let diff = match turn {
+        Turn::White => -diff,
+        _ => diff,
+    };
+    match variant {
+        Variant::Othello => diff,
+        Variant::Anti => -diff,
+    }
+}
+
+// Square weights and mobility, scaled to roughly a disc per ten points.
+// `Anti` turns the weights around, since corners and edges make discs
+// that can never be given away, but keeps wanting moves to choose from.
+fn evaluate(board: &Board, turn: Turn, mobility: usize, variant: Variant) -> i32 {
+    let mine = turn.disc();
+    let theirs = turn.opponent().disc();
+    let mut score = 0;
+    for row in 1..=board.size {
+        for col in 1..=board.size {
+            let cell = &board.cells[row][col];
+            if *cell == mine {
+                score += weight(board.size, row, col);
+            } else if *cell == theirs {
+                score -= weight(board.size, row, col);
+            }
+        }
+    }
+    if variant == Variant::Anti {
+        score = -score;
+    }
+    let their_mobility = Move::legal_moves(board, turn.opponent()).len();
+    score += 5 * (mobility as i32 - their_mobility as i32);
+    score / 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Notation;
+    use crate::transcript;
+
+    #[test]
+    fn solves_an_endgame_exactly() {
+        // A random game with eight empties left. Its result with best play
+        // was checked against a plain minimax search.
+        let moves = "c4c5f6c3b5g7e3e6c2f3g3a5h8b3f4f2b4f5f7h3a3d2e2e1a6e7d7\
+                     c1c6g8f1g4d1b6b1d3g6b7f8a7c7h6a8b2g5g2a1d6h2h5a4d8";
+        let moves = transcript::parse(moves, Notation::Standard).unwrap();
+        let position = transcript::replay(&moves).unwrap().pop().unwrap();
+        assert_eq!(empties(&position.board), 8);
+        let (best, score) = Engine::new(None).solve(&position.board, position.turn);
+        assert!(best.is_some());
+        assert_eq!(score, 10);
+
+        // The same position, played for the fewest discs.
+        let mut engine = Engine::new(None);
+        engine.variant = Variant::Anti;
+        assert_eq!(engine.solve(&position.board, position.turn).1, -26);
+
+        // With a table, shared by two searches in a row, nothing changes
+        // but the work done the second time around.
+        let table = Arc::new(Transpositions::new(1 << 16));
+        let mut first = Engine::with_table(None, table.clone());
+        assert_eq!(first.solve(&position.board, position.turn).1, 10);
+        let mut second = Engine::with_table(None, table);
+        let (best, score) = second.solve(&position.board, position.turn);
+        assert_eq!(score, 10);
+        assert!(second.nodes < first.nodes);
+
+        // And the line it expects plays out to the end of the game.
+        let line = second.pv(&position.board, position.turn, best.unwrap(), 64);
+        let mut moves = moves;
+        moves.extend(line);
+        let last = transcript::replay(&moves).unwrap().pop().unwrap();
+        assert!(last.is_over());
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+
+// Why a line that was typed in isn't a move or a command.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    // Two characters, but not a square in the current notation.
+    NotASquare(String),
+    Unknown(String),
+}
+
+// Why a move can't be played where it was asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IllegalMove {
+    Occupied,
+    Blocked,
+    NoFlips,
+}
+
+// Why no move could be read at all.
+#[derive(Debug)]
+pub enum InputError {
+    // stdin was closed, e.g. with Ctrl-D or at the end of piped input.
+    Eof,
+    Io(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => f.write_str("no move entered"),
+            ParseError::NotASquare(s) => write!(f, "`{}` is not a square", s),
+            ParseError::Unknown(s) => write!(f, "`{}` is not a move or a command", s),
+        }
+    }
+}
+
+impl IllegalMove {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IllegalMove::Occupied => "square occupied",
+            IllegalMove::Blocked => "square blocked",
+            IllegalMove::NoFlips => "no discs flipped",
+        }
+    }
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Eof => f.write_str("input closed"),
+            InputError::Io(e) => write!(f, "failed to read input: {}", e),
+        }
+    }
+}
@@ -1,12 +1,21 @@
+use std::fmt;
 use std::io;
-use std::thread::sleep;
-use std::time::Duration;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
 
-use colored::Colorize;
+use clap::ValueEnum;
+use serde::Deserialize;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use crate::board::{Board, Cell};
+use crate::board::{Board, Cell, MAX_SIZE};
+use crate::config;
+use crate::error::{IllegalMove, InputError, ParseError};
+use crate::theme;
 use crate::{check, flip};
 
 #[derive(Clone, Copy, PartialEq)]
@@ -16,13 +25,27 @@ pub enum Turn {
     Neither,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum Move {
     Play(usize, usize),
     Pass,
     Skip,
     Resign,
-    Win(u8, u8),
+    Timeout,
+    ToggleHints,
+    Quit,
+    Win(u16, u16),
+}
+
+// How squares are written. `Classic` is column then row, `3d`, matching the
+// board's labels of `1`-`8` across and `A`-`H` down. `Standard` is the usual
+// Othello `d3`, with the board labelled `a`-`h` across and `1`-`8` down.
+// Larger boards carry on with `9`-`16` and `i`-`p`.
+#[derive(Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Notation {
+    Classic,
+    Standard,
 }
 
 #[derive(EnumIter)]
@@ -37,9 +60,155 @@ enum Direction {
     DownRight,
 }
 
+const NUMBERS: [&str; MAX_SIZE] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+];
+const LETTERS: [&str; MAX_SIZE] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p",
+];
+const CAPITALS: [&str; MAX_SIZE] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P",
+];
+
+impl Notation {
+    // Reads squares as typed, e.g. `3d`, or several run together like
+    // `d3c4` or `j10a1`, into `(row, col)`s on a board `size` squares across.
+    // Returns `None` unless all of `text` is squares.
+    pub fn parse(self, text: &str, size: usize) -> Option<Vec<(usize, usize)>> {
+        let mut chars = text.chars().peekable();
+        let mut squares = Vec::new();
+        while chars.peek().is_some() {
+            let (number, letter) = match self {
+                Notation::Classic => {
+                    let number = take_number(&mut chars)?;
+                    (number, take_letter(&mut chars)?)
+                }
+                Notation::Standard => {
+                    let letter = take_letter(&mut chars)?;
+                    (take_number(&mut chars)?, letter)
+                }
+            };
+            if !(1..=size).contains(&number) || !(1..=size).contains(&letter) {
+                return None;
+            }
+            squares.push(match self {
+                Notation::Classic => (letter, number),
+                Notation::Standard => (number, letter),
+            });
+        }
+        Some(squares)
+    }
+
+    pub fn format(self, row: usize, col: usize) -> String {
+        match self {
+            Notation::Classic => format!("{}{}", col, LETTERS[row - 1]),
+            Notation::Standard => format!("{}{}", LETTERS[col - 1], row),
+        }
+    }
+
+    // The labels across the top of a board `size` squares across,
+    // then down its side.
+    pub fn labels(self, size: usize) -> (&'static [&'static str], &'static [&'static str]) {
+        match self {
+            Notation::Classic => (&NUMBERS[..size], &CAPITALS[..size]),
+            Notation::Standard => (&LETTERS[..size], &NUMBERS[..size]),
+        }
+    }
+}
+
+// One or two digits, e.g. the `10` of `j10`.
+fn take_number(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut number = chars.next()?.to_digit(10)? as usize;
+    if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        chars.next();
+        number = number * 10 + digit as usize;
+    }
+    Some(number)
+}
+
+// A column or row letter, counting `a` as 1.
+fn take_letter(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let letter = chars.next()?.to_ascii_lowercase();
+    letter
+        .is_ascii_lowercase()
+        .then(|| letter as usize - 'a' as usize + 1)
+}
+
+// Moves are written the way they are typed in, e.g. `3d`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Play(row, col) => f.write_str(&config::current().notation.format(*row, *col)),
+            Move::Pass => f.write_str("pass"),
+            Move::Skip => f.write_str("skip"),
+            Move::Resign => f.write_str("resign"),
+            Move::Timeout => f.write_str("time"),
+            Move::ToggleHints => f.write_str("hints"),
+            Move::Quit => f.write_str("quit"),
+            Move::Win(b, w) => write!(f, "{}-{}", b, w),
+        }
+    }
+}
+
+impl Turn {
+    pub fn opponent(self) -> Turn {
+        match self {
+            Turn::Black => Turn::White,
+            Turn::White => Turn::Black,
+            Turn::Neither => Turn::Neither,
+        }
+    }
+
+    // The disc a player places. `Neither` has no disc of its own,
+    // so it gets `Illegal`, which no move check treats as playable.
+    pub fn disc(self) -> Cell {
+        match self {
+            Turn::Black => Cell::Black,
+            Turn::White => Cell::White,
+            Turn::Neither => Cell::Illegal,
+        }
+    }
+}
+
 impl Move {
-    pub fn handle_move(board: &mut Board, turn: Turn) -> (Turn, Option<Move>) {
-        match Self::get_move(board, turn) {
+    // Returns every cell `turn` could play on, as `(row, col)`.
+    pub fn legal_moves(board: &Board, turn: Turn) -> Vec<(usize, usize)> {
+        let mut moves = Vec::new();
+        for row in 1..=board.size {
+            for col in 1..=board.size {
+                if board.cells[row][col] == Cell::Okay {
+                    // `flip_discs` flips as a side effect, so try each move on a copy.
+                    let mut next = board.clone();
+                    if Self::flip_discs(&mut next, turn, row, col) {
+                        moves.push((row, col));
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    // The board after `turn` plays at `(row, col)`, or `None` if that isn't legal.
+    pub fn after(board: &Board, turn: Turn, row: usize, col: usize) -> Option<Board> {
+        let mut next = board.clone();
+        if !Self::is_valid_move(&mut next, turn, row, col) {
+            return None;
+        }
+        next.cells[row][col] = turn.disc();
+        next.validate_cells();
+        Some(next)
+    }
+
+    // Whether `turn` has anywhere to play.
+    // `check_playablity` is phrased from the other player's side, hence the flip.
+    pub fn has_moves(board: &Board, turn: Turn) -> bool {
+        Self::check_playablity(board, turn.opponent())
+    }
+
+    // Carries out a move that has already been read and checked.
+    // For `Move::Play`, the discs must already have been flipped by `is_valid_move`.
+    pub fn apply_move(board: &mut Board, turn: Turn, mv: Move) -> (Turn, Option<Move>) {
+        match mv {
             Move::Play(r, c) => match turn {
                 Turn::Black => {
                     board.cells[r][c] = Cell::Black;
@@ -89,7 +258,7 @@ impl Move {
                         board.cells[0][0] = Cell::Indicator(Turn::White);
                         (Turn::White, Some(Move::Pass))
                     } else {
-                        println!("{}", "Cannot pass.".red());
+                        println!("{}", theme::current().error.paint("Cannot pass."));
                         sleep(Duration::from_secs_f32(1.5));
                         (Turn::Black, None)
                     }
@@ -99,7 +268,7 @@ impl Move {
                         board.cells[0][0] = Cell::Indicator(Turn::Black);
                         (Turn::Black, Some(Move::Pass))
                     } else {
-                        println!("{}", "Cannot pass.".red());
+                        println!("{}", theme::current().error.paint("Cannot pass."));
                         sleep(Duration::from_secs_f32(1.5));
                         (Turn::White, None)
                     }
@@ -111,15 +280,107 @@ impl Move {
                 Turn::White => (Turn::White, Some(Move::Resign)),
                 _ => (Turn::Neither, None),
             },
+            Move::Timeout => match turn {
+                Turn::Black => (Turn::Black, Some(Move::Timeout)),
+                Turn::White => (Turn::White, Some(Move::Timeout)),
+                _ => (Turn::Neither, None),
+            },
+            Move::Quit => (turn, Some(Move::Quit)),
+            Move::ToggleHints => {
+                board.hints = !board.hints;
+                (turn, None)
+            }
             _ => (Turn::Neither, None),
         }
     }
 }
 
-trait InputHandler {
-    fn get_move(board: &mut Board, turn: Turn) -> Move;
-    fn get_input() -> Move;
+// Reads a line as typed in, e.g. `3d`, `p` or `q`, for a board `size` squares across.
+pub fn parse_input(input: &str, notation: Notation, size: usize) -> Result<Move, ParseError> {
+    let chars = input
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<Vec<_>>();
+    match chars.as_slice() {
+        [] => Err(ParseError::Empty),
+        ['p'] => Ok(Move::Pass),
+        ['r'] => Ok(Move::Resign),
+        ['h'] => Ok(Move::ToggleHints),
+        ['q'] => Ok(Move::Quit),
+        _ => match notation
+            .parse(&chars.iter().collect::<String>(), size)
+            .as_deref()
+        {
+            Some(&[(row, col)]) => Ok(Move::Play(row, col)),
+            // Too short to be anything else, so probably a mistyped square.
+            _ if chars.len() <= 3 => Err(ParseError::NotASquare(input.trim().to_string())),
+            _ => Err(ParseError::Unknown(input.trim().to_string())),
+        },
+    }
+}
+
+// Lines from stdin are read on a background thread, so that waiting
+// for a move can give up once the player's flag falls.
+//
+// Returns `None` if `deadline` passes before a line comes in.
+pub fn read_line(deadline: Option<Instant>) -> Result<Option<String>, InputError> {
+    static LINES: OnceLock<Mutex<Receiver<io::Result<String>>>> = OnceLock::new();
+    let lines = LINES
+        .get_or_init(|| Mutex::new(spawn_reader(|line| io::stdin().read_line(line))))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    receive(&lines, deadline)
+}
+
+// Sends every line `read_line` reads down a channel, which closes at EOF
+// or after the first error.
+fn spawn_reader(
+    mut read_line: impl FnMut(&mut String) -> io::Result<usize> + Send + 'static,
+) -> Receiver<io::Result<String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        let mut line = String::new();
+        // The channel closing is how the other end learns of EOF.
+        let read = match read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => Ok(line),
+            Err(e) => Err(e),
+        };
+        let failed = read.is_err();
+        if tx.send(read).is_err() || failed {
+            break;
+        }
+    });
+    rx
+}
+
+fn receive(
+    lines: &Receiver<io::Result<String>>,
+    deadline: Option<Instant>,
+) -> Result<Option<String>, InputError> {
+    let line = match deadline {
+        None => lines.recv().map_err(|_| InputError::Eof)?,
+        Some(deadline) => {
+            match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(InputError::Eof),
+            }
+        }
+    };
+    line.map(Some).map_err(InputError::Io)
+}
+
+pub trait InputHandler {
+    fn get_move(
+        board: &mut Board,
+        turn: Turn,
+        deadline: Option<Instant>,
+    ) -> Result<Move, InputError>;
+    fn get_input(deadline: Option<Instant>) -> Result<Move, InputError>;
     fn is_valid_move(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool;
+    fn check_move(board: &mut Board, turn: Turn, row: usize, col: usize)
+        -> Result<(), IllegalMove>;
     fn flip_discs(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool;
     fn try_flipping_up(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool;
     fn try_flipping_down(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool;
@@ -132,63 +393,73 @@ trait InputHandler {
 }
 
 impl InputHandler for Move {
-    fn get_move(board: &mut Board, turn: Turn) -> Move {
-        match Self::get_input() {
-            Move::Play(row, col) => {
-                // This is where flipping discs happens if and only if
-                // there are flippable dics, and returns `Move::Play(_, _)`.
-                //
-                // Otherwise, prompt the player to input `row` and `col` again.
-                if Self::is_valid_move(board, turn, row, col) {
-                    Move::Play(row, col)
-                } else {
-                    println!("{}", "Invalid move. Please try again.".red());
-                    Self::get_move(board, turn)
+    fn get_move(
+        board: &mut Board,
+        turn: Turn,
+        deadline: Option<Instant>,
+    ) -> Result<Move, InputError> {
+        loop {
+            match Self::get_input(deadline)? {
+                Move::Play(row, col) => {
+                    // This is where flipping discs happens if and only if
+                    // there are flippable dics, and returns `Move::Play(_, _)`.
+                    //
+                    // Otherwise, prompt the player to input `row` and `col` again.
+                    match Self::check_move(board, turn, row, col) {
+                        Ok(()) => return Ok(Move::Play(row, col)),
+                        Err(e) => println!(
+                            "{}",
+                            theme::current()
+                                .error
+                                .paint(&format!("Invalid move: {}. Please try again.", e))
+                        ),
+                    }
                 }
+                mv => return Ok(mv),
             }
-            mv => mv,
         }
     }
 
-    fn get_input() -> Move {
-        println!("Enter your move. (Example: 3d)");
-        println!("Enter `p` to pass or `r` to resign.");
-
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("failed to read line");
+    fn get_input(deadline: Option<Instant>) -> Result<Move, InputError> {
+        let notation = config::current().notation;
+        loop {
+            println!("Enter your move. (Example: {})", notation.format(4, 3));
+            println!("Enter `p` to pass or `r` to resign.");
+            println!("Enter `h` to show or hide legal moves, or `q` to save and quit.");
 
-        let input = input
-            .chars()
-            .filter(|c| c.is_ascii_alphanumeric())
-            .collect::<Vec<_>>();
-
-        match input.len() {
-            1 => match input[0] {
-                'p' => Move::Pass,
-                'r' => Move::Resign,
-                _ => Self::get_input(),
-            },
-            2 => match input[0] {
-                c if ('1'..='8').contains(&c) => match input[1].to_ascii_lowercase() {
-                    r if ('a'..='h').contains(&r) => {
-                        let row = r.to_digit(18).unwrap() as usize - 9;
-                        let col = c.to_digit(10).unwrap() as usize;
-                        Move::Play(row, col)
-                    }
-                    _ => Self::get_input(),
-                },
-                _ => Self::get_input(),
-            },
-            _ => Self::get_input(),
+            let input = match read_line(deadline)? {
+                Some(input) => input,
+                None => return Ok(Move::Timeout),
+            };
+            match parse_input(&input, notation, config::current().size) {
+                Ok(mv) => return Ok(mv),
+                Err(e) => println!(
+                    "{}",
+                    theme::current()
+                        .error
+                        .paint(&format!("Invalid input: {}. Please try again.", e))
+                ),
+            }
         }
     }
 
     fn is_valid_move(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
-        match board.cells[row][col] {
-            Cell::Okay => Self::flip_discs(board, turn, row, col),
-            _ => false,
+        Self::check_move(board, turn, row, col).is_ok()
+    }
+
+    // Like `is_valid_move`, flipping the discs if the move is legal,
+    // but says what is wrong with it if not.
+    fn check_move(
+        board: &mut Board,
+        turn: Turn,
+        row: usize,
+        col: usize,
+    ) -> Result<(), IllegalMove> {
+        match board.cells[row][col].clone() {
+            Cell::Black | Cell::White => Err(IllegalMove::Occupied),
+            Cell::Blocked => Err(IllegalMove::Blocked),
+            Cell::Okay if Self::flip_discs(board, turn, row, col) => Ok(()),
+            _ => Err(IllegalMove::NoFlips),
         }
     }
 
@@ -205,7 +476,7 @@ impl InputHandler for Move {
 
     fn try_flipping_up(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (1..=2, _) => false,
+            (r, _) if r <= 2 => false,
             _ => match turn {
                 Turn::Black => flip!(board, Cell::Black, Cell::White, Direction::Up, row, col),
                 Turn::White => flip!(board, Cell::White, Cell::Black, Direction::Up, row, col),
@@ -216,7 +487,7 @@ impl InputHandler for Move {
 
     fn try_flipping_down(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (7..=8, _) => false,
+            (r, _) if r + 2 > board.size => false,
             _ => match turn {
                 Turn::Black => flip!(board, Cell::Black, Cell::White, Direction::Down, row, col),
                 Turn::White => flip!(board, Cell::White, Cell::Black, Direction::Down, row, col),
@@ -227,7 +498,7 @@ impl InputHandler for Move {
 
     fn try_flipping_left(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (_, 1..=2) => false,
+            (_, c) if c <= 2 => false,
             _ => match turn {
                 Turn::Black => flip!(board, Cell::Black, Cell::White, Direction::Left, row, col),
                 Turn::White => flip!(board, Cell::White, Cell::Black, Direction::Left, row, col),
@@ -238,7 +509,7 @@ impl InputHandler for Move {
 
     fn try_flipping_right(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (_, 7..=8) => false,
+            (_, c) if c + 2 > board.size => false,
             _ => match turn {
                 Turn::Black => flip!(board, Cell::Black, Cell::White, Direction::Right, row, col),
                 Turn::White => flip!(board, Cell::White, Cell::Black, Direction::Right, row, col),
@@ -249,7 +520,7 @@ impl InputHandler for Move {
 
     fn try_flipping_up_left(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (1..=2, _) | (_, 1..=2) => false,
+            (r, c) if r <= 2 || c <= 2 => false,
             _ => match turn {
                 Turn::Black => flip!(board, Cell::Black, Cell::White, Direction::UpLeft, row, col),
                 Turn::White => flip!(board, Cell::White, Cell::Black, Direction::UpLeft, row, col),
@@ -260,7 +531,7 @@ impl InputHandler for Move {
 
     fn try_flipping_up_right(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (1..=2, _) | (_, 7..=8) => false,
+            (r, c) if r <= 2 || c + 2 > board.size => false,
             _ => match turn {
                 Turn::Black => flip!(
                     board,
@@ -285,7 +556,7 @@ impl InputHandler for Move {
 
     fn try_flipping_down_left(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (7..=8, _) | (_, 1..=2) => false,
+            (r, c) if r + 2 > board.size || c <= 2 => false,
             _ => match turn {
                 Turn::Black => flip!(
                     board,
@@ -310,7 +581,7 @@ impl InputHandler for Move {
 
     fn try_flipping_down_right(board: &mut Board, turn: Turn, row: usize, col: usize) -> bool {
         match (row, col) {
-            (7..=8, _) | (_, 7..=8) => false,
+            (r, c) if r + 2 > board.size || c + 2 > board.size => false,
             _ => match turn {
                 Turn::Black => flip!(
                     board,
@@ -334,9 +605,9 @@ impl InputHandler for Move {
     }
 }
 
-trait PlayabilityChecker {
+pub trait PlayabilityChecker {
     fn check_playablity(board: &Board, turn: Turn) -> bool;
-    fn check_end_game(board: &Board) -> (bool, Turn, Option<u8>, Option<u8>);
+    fn check_end_game(board: &Board) -> (bool, Turn, Option<u16>, Option<u16>);
 }
 
 impl PlayabilityChecker for Move {
@@ -348,7 +619,7 @@ impl PlayabilityChecker for Move {
         }
     }
 
-    fn check_end_game(board: &Board) -> (bool, Turn, Option<u8>, Option<u8>) {
+    fn check_end_game(board: &Board) -> (bool, Turn, Option<u16>, Option<u16>) {
         if !Self::check_playablity(board, Turn::Black)
             && !Self::check_playablity(board, Turn::White)
         {
@@ -357,3 +628,76 @@ impl PlayabilityChecker for Move {
         (false, Turn::Neither, None, None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::Setup;
+    use std::io::{BufRead, Cursor, Error, ErrorKind};
+
+    #[test]
+    fn parses_moves_and_commands() {
+        let parse = |text| parse_input(text, Notation::Standard, 8);
+        assert!(parse("d3\n") == Ok(Move::Play(3, 4)));
+        assert!(parse(" D3 ") == parse("d3"));
+        assert!(parse_input("4c", Notation::Classic, 8) == parse("d3"));
+        assert!(parse("p") == Ok(Move::Pass));
+        assert!(parse("r\n") == Ok(Move::Resign));
+        assert!(parse("h") == Ok(Move::ToggleHints));
+        assert!(parse("q") == Ok(Move::Quit));
+        assert!(parse("j10") == Err(ParseError::NotASquare("j10".to_string())));
+        assert!(parse_input("j10", Notation::Standard, 10) == Ok(Move::Play(10, 10)));
+    }
+
+    #[test]
+    fn says_why_input_is_not_a_move() {
+        let parse = |text| parse_input(text, Notation::Standard, 8);
+        assert!(parse("\n") == Err(ParseError::Empty));
+        assert!(parse(" - \n") == Err(ParseError::Empty));
+        assert!(parse("z9\n") == Err(ParseError::NotASquare("z9".to_string())));
+        assert!(parse("i1") == Err(ParseError::NotASquare("i1".to_string())));
+        assert!(parse("resign") == Err(ParseError::Unknown("resign".to_string())));
+    }
+
+    #[test]
+    fn says_why_a_square_cannot_be_played() {
+        let mut board = Setup::standard(8).board;
+        board.cells[1][8] = Cell::Blocked;
+        let check = |board: &mut Board, row, col| Move::check_move(board, Turn::Black, row, col);
+        assert_eq!(check(&mut board, 4, 4), Err(IllegalMove::Occupied));
+        assert_eq!(check(&mut board, 1, 8), Err(IllegalMove::Blocked));
+        assert_eq!(check(&mut board, 1, 1), Err(IllegalMove::NoFlips));
+        let (row, col) = Move::legal_moves(&board, Turn::Black)[0];
+        assert_eq!(check(&mut board, row, col), Ok(()));
+    }
+
+    #[test]
+    fn ends_input_at_eof() {
+        let mut input = Cursor::new("f5\nd6");
+        let lines = spawn_reader(move |line| input.read_line(line));
+        assert_eq!(receive(&lines, None).unwrap().as_deref(), Some("f5\n"));
+        assert_eq!(receive(&lines, None).unwrap().as_deref(), Some("d6"));
+        assert!(matches!(receive(&lines, None), Err(InputError::Eof)));
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(matches!(
+            receive(&lines, Some(deadline)),
+            Err(InputError::Eof)
+        ));
+    }
+
+    #[test]
+    fn ends_input_after_a_failed_read() {
+        let lines = spawn_reader(|_| Err(Error::new(ErrorKind::InvalidData, "not UTF-8")));
+        assert!(matches!(receive(&lines, None), Err(InputError::Io(_))));
+        assert!(matches!(receive(&lines, None), Err(InputError::Eof)));
+    }
+
+    #[test]
+    fn gives_up_waiting_at_the_deadline() {
+        let (tx, lines) = mpsc::channel();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert!(matches!(receive(&lines, Some(deadline)), Ok(None)));
+        tx.send(Ok("p\n".to_string())).unwrap();
+        assert!(matches!(receive(&lines, Some(deadline)), Ok(Some(_))));
+    }
+}
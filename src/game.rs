@@ -1,5 +1,21 @@
-use crate::board::Board;
-use crate::events::{Move, Turn};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+
+use crate::archive;
+use crate::clock::{Clock, TimeControl};
+use crate::config;
+use crate::engine;
+use crate::error::InputError;
+use crate::events::{InputHandler, Move, Turn};
+use crate::profiles;
+use crate::protocol::{End, Outcome};
+use crate::review;
+use crate::setup;
+use crate::theme;
+use crate::transcript;
+
+// Where `q` saves the game, in the current directory.
+pub const SAVE_FILE: &str = "othello-save.txt";
 
 pub enum Game {
     On,
@@ -7,37 +23,207 @@ pub enum Game {
 }
 
 impl Game {
-    pub fn start() {
-        let mut board = Board::new();
+    // Plays a game, picking up after `moves` if there are any.
+    pub fn start(time_control: Option<TimeControl>, moves: &[Move]) -> Result<(), String> {
+        let position = transcript::replay(moves)?.pop().expect("starting position");
+        if position.is_over() {
+            return Err("the game is already over".to_string());
+        }
+        let mut board = position.board;
+        let mut clock = time_control.map(Clock::new);
         let mut mode = Game::On;
-        let mut turn = Turn::Black;
-        let mut mv: Option<Move> = None;
-        board.draw(turn, mv);
-        loop {
-            match mode {
-                Game::On => match turn {
-                    Turn::Black => {
-                        let res = Move::handle_move(&mut board, turn);
-                        turn = res.0;
-                        mv = res.1;
-                        board.draw(turn, mv);
+        let mut turn = position.turn;
+        let mut mv = position.mv;
+        let mut history = moves.to_vec();
+        let mut outcome = None;
+        board.draw(turn, mv, clock.as_ref());
+        while let Game::On = mode {
+            match turn {
+                Turn::Black | Turn::White => {
+                    let before = board.clone();
+                    let deadline = clock.as_mut().map(|c| c.start(turn));
+                    let played = match config::current().player(turn).ai {
+                        Some(level) => {
+                            let mv = engine::pick(&board, turn, level, clock.as_ref());
+                            if let Move::Play(row, col) = mv {
+                                Move::is_valid_move(&mut board, turn, row, col);
+                            }
+                            mv
+                        }
+                        None => match Move::get_move(&mut board, turn, deadline) {
+                            Ok(mv) => mv,
+                            Err(e) => {
+                                offer_save(&history, e);
+                                return Ok(());
+                            }
+                        },
+                    };
+                    if let Move::Quit = played {
+                        save_and_quit(&history);
+                        return Ok(());
+                    }
+                    let res = Move::apply_move(&mut board, turn, played);
+                    board.record_changes(&before);
+                    let completed = completed(turn, &res);
+                    // A move that squeaked in just as the flag fell still loses.
+                    if let Some(clock) = clock.as_mut() {
+                        if !clock.stop(turn, completed) {
+                            mv = Some(Move::Timeout);
+                            outcome = profiles::outcome(&board, turn, Move::Timeout);
+                            board.draw(turn, mv, Some(clock));
+                            turn = Turn::Neither;
+                            continue;
+                        }
                     }
-                    Turn::White => {
-                        let res = Move::handle_move(&mut board, turn);
-                        turn = res.0;
-                        mv = res.1;
-                        board.draw(turn, mv);
+                    if completed && matches!(played, Move::Play(_, _) | Move::Pass) {
+                        history.push(played);
                     }
-                    Turn::Neither => mode = Game::Off,
-                },
-                Game::Off => {
-                    break;
+                    turn = res.0;
+                    mv = res.1;
+                    board.draw(turn, mv, clock.as_ref());
                 }
+                Turn::Neither => mode = Game::Off,
             }
-            // When either `Resign or Win` happens, game ends.
-            if let Some(Move::Resign) | Some(Move::Win(_, _)) = mv {
+            // When either `Resign, Timeout or Win` happens, game ends.
+            if let Some(end @ (Move::Resign | Move::Timeout | Move::Win(_, _))) = mv {
+                if outcome.is_none() {
+                    outcome = profiles::outcome(&board, turn, end);
+                }
                 turn = Turn::Neither
             }
         }
+        if let Some(outcome) = outcome {
+            record(&outcome, &history);
+            if matches!(outcome.end, End::Finished | End::Resigned) {
+                review::offer(&history);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Anything that hands the turn over, ends the game, or leaves the
+// opponent to skip counts as a completed move, as opposed to a pass that
+// was turned down.
+fn completed(turn: Turn, res: &(Turn, Option<Move>)) -> bool {
+    res.0 != turn || matches!(res.1, Some(Move::Skip | Move::Win(_, _)))
+}
+
+// Counts a finished game towards both players' profiles, and keeps it
+// in the archive.
+fn record(outcome: &Outcome, history: &[Move]) {
+    let config = config::current();
+    let black = profiles::key(&config.black);
+    let white = profiles::key(&config.white);
+    let failed = |e: String| {
+        eprintln!(
+            "{}",
+            theme::current()
+                .error
+                .paint(&format!("Failed to record the game: {}", e))
+        )
+    };
+    match profiles::record_game(&black, &white, outcome) {
+        Ok(Some(ratings)) => println!("{}", ratings),
+        Ok(None) => {}
+        Err(e) => failed(e),
+    }
+    match archive::store(&black, &white, outcome, history) {
+        Ok(Some(id)) => println!("Archived as game {}.", id),
+        Ok(None) => {}
+        Err(e) => failed(e),
+    }
+}
+
+// Writes the moves so far where `othello play --load` can pick them up.
+// A game from a position of its own starts with that position.
+pub fn save(history: &[Move]) -> io::Result<()> {
+    let mut text = String::new();
+    if !setup::start().is_standard() {
+        text = format!("{}\n", setup::start());
+    }
+    text.push_str(&transcript::format(history, config::current().notation));
+    fs::write(SAVE_FILE, text + "\n")
+}
+
+pub fn save_and_quit(history: &[Move]) {
+    match save(history) {
+        Ok(()) => println!(
+            "Game saved to {}. Resume it with `othello play --load {}`.",
+            SAVE_FILE, SAVE_FILE
+        ),
+        Err(e) => eprintln!(
+            "{}",
+            theme::current()
+                .error
+                .paint(&format!("Failed to save the game: {}", e))
+        ),
+    }
+}
+
+// Input ran out in the middle of a game. On a terminal, where Ctrl-D
+// doesn't close stdin for good, the player gets asked whether to save.
+fn offer_save(history: &[Move], error: InputError) {
+    println!();
+    println!(
+        "{}",
+        theme::current()
+            .error
+            .paint(&format!("Game stopped: {}.", error))
+    );
+    if history.is_empty() || !io::stdin().is_terminal() {
+        return;
+    }
+    print!("Save the game before quitting? [y/N] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y") {
+        save_and_quit(history);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::Setup;
+
+    // Plays the first legal move every turn, keeping the history the way
+    // `Game::start` does. Also says whether the last mover won.
+    fn play_out(start: &Setup) -> (Vec<Move>, bool) {
+        let (mut board, mut turn) = (start.board.clone(), start.turn);
+        let mut history = Vec::new();
+        loop {
+            let (row, col) = Move::legal_moves(&board, turn)[0];
+            Move::is_valid_move(&mut board, turn, row, col);
+            let res = Move::apply_move(&mut board, turn, Move::Play(row, col));
+            if completed(turn, &res) {
+                history.push(Move::Play(row, col));
+            }
+            match res {
+                (_, Some(Move::Win(black, white))) => {
+                    let won = match turn {
+                        Turn::Black => black > white,
+                        _ => white > black,
+                    };
+                    return (history, won);
+                }
+                (next, _) => turn = next,
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_move_that_ends_the_game() {
+        let mut mover_won = false;
+        for size in [4, 6, 8] {
+            let start = Setup::standard(size);
+            let (history, won) = play_out(&start);
+            let positions = transcript::replay_from(&start, &history).unwrap();
+            let end = positions.last().unwrap();
+            assert!(end.is_over());
+            assert_eq!(end.board.move_number(&start.board), history.len() + 1);
+            mover_won |= won;
+        }
+        assert!(mover_won, "no game ended on the winner's move");
     }
 }
@@ -0,0 +1,134 @@
+use crate::events::{Move, Notation};
+use crate::setup::Setup;
+use crate::transcript::Game;
+
+// Reads games in the Generic Game Format that GGS and other Othello
+// servers keep their records in, like
+//
+//     (;GM[Othello]PB[alice]PW[bob]TY[8]BO[8 -------- -------- -------- ---O*---
+//     ---*O--- -------- -------- -------- *]B[f5//0.01]W[d6]B[c3]W[PA];)
+//
+// Only the players, the starting board and the moves are read. Other
+// properties, and every game after the first in a file, are left alone.
+
+// Whether `text` looks like a GGF game rather than a plain transcript.
+pub fn is_ggf(text: &str) -> bool {
+    text.trim_start().starts_with("(;")
+}
+
+pub fn parse(text: &str) -> Result<Game, String> {
+    let body = text
+        .trim_start()
+        .strip_prefix("(;")
+        .ok_or("a GGF game starts with `(;`")?;
+    let mut game = Game {
+        black: None,
+        white: None,
+        start: None,
+        moves: Vec::new(),
+    };
+    let mut rest = body;
+    let mut board = None;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with(";)") || rest.is_empty() {
+            break;
+        }
+        let open = rest
+            .find('[')
+            .ok_or_else(|| format!("expected a property at `{}`", excerpt(rest)))?;
+        let close = rest[open..]
+            .find(']')
+            .map(|i| open + i)
+            .ok_or("a property is missing its `]`")?;
+        let (tag, value) = (rest[..open].trim(), &rest[open + 1..close]);
+        rest = &rest[close + 1..];
+        match tag {
+            "PB" => game.black = Some(value.to_string()),
+            "PW" => game.white = Some(value.to_string()),
+            "BO" => board = Some(value.to_string()),
+            "B" | "W" => game.moves.push(parse_move(value, &board)?),
+            _ => {}
+        }
+    }
+
+    if let Some(board) = board {
+        game.start = Some(parse_board(&board)?);
+    }
+    Ok(game)
+}
+
+// `BO[8 <rows> <side>]`: the size, then the squares as Setup reads them.
+fn parse_board(value: &str) -> Result<Setup, String> {
+    let (size, squares) = value
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or("empty BO[]")?;
+    let setup = Setup::read(squares).map_err(|e| format!("BO[]: {}", e))?;
+    if size.parse() != Ok(setup.board.size) {
+        return Err(format!("BO[] says {} squares across, but isn't", size));
+    }
+    Ok(setup)
+}
+
+// A move like `f5`, `F5//1.23` or `PA` for a pass, on the board so far.
+fn parse_move(value: &str, board: &Option<String>) -> Result<Move, String> {
+    let square = value
+        .split('/')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if square == "pa" || square == "pass" {
+        return Ok(Move::Pass);
+    }
+    let size = board
+        .as_deref()
+        .and_then(|board| board.split_whitespace().next())
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(8);
+    match Notation::Standard.parse(&square, size).as_deref() {
+        Some(&[(row, col)]) => Ok(Move::Play(row, col)),
+        _ => Err(format!("`{}` is not a move", value)),
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    text.chars().take(12).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript;
+
+    #[test]
+    fn reads_a_ggf_game() {
+        let text = "(;GM[Othello]PC[GGS/os]PB[alice]PW[bob]RE[+0.000]TY[8]\n\
+            BO[8 -------- -------- -------- ---O*--- ---*O--- -------- -------- -------- *]\n\
+            B[F5//0.01]W[d6/-1.5/2]B[c3];)";
+        assert!(is_ggf(text));
+        let game = parse(text).unwrap();
+        assert_eq!(game.black.as_deref(), Some("alice"));
+        assert_eq!(game.white.as_deref(), Some("bob"));
+        assert!(game.start.unwrap().is_standard());
+        let moves = game.moves.iter().map(|mv| match mv {
+            Move::Play(row, col) => Notation::Standard.format(*row, *col),
+            _ => "pass".to_string(),
+        });
+        assert_eq!(moves.collect::<Vec<_>>(), ["f5", "d6", "c3"]);
+
+        // Black has no move after f8 and the record says so.
+        let text = "(;GM[Othello]PB[alice]PW[bob]TY[8]\
+            B[e6]W[f6]B[d3]W[e7]B[e8]W[d8]B[g6]W[f8]B[PA]W[g5];)";
+        let game = parse(text).unwrap();
+        assert!(game.moves[8] == Move::Pass);
+        let positions = transcript::replay_from(&Setup::standard(8), &game.moves).unwrap();
+        assert_eq!(positions.len(), game.moves.len());
+        let last = positions.last().unwrap();
+        assert!(last.played == Some(Move::Play(5, 7)));
+
+        assert!(parse("(;B[z9];)").is_err());
+        assert!(!is_ggf("f5 d6 c3"));
+    }
+}
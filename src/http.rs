@@ -0,0 +1,430 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::board::Board;
+use crate::engine::{self, Engine, Transpositions, MAX_LEVEL, MAX_SOLVE_EMPTIES};
+use crate::events::{Move, Notation, Turn};
+use crate::protocol;
+use crate::setup::{self, Setup};
+use crate::transcript;
+
+// `othello http` serves the engine to other programs, as JSON over HTTP:
+//
+//     POST /analyze   {"position": "...", "depth": 10, "time_ms": 2000}
+//                     scores every legal move, best first, with the line
+//                     expected after the best one
+//     POST /solve     {"position": "..."}
+//                     the exact result with best play, for positions with
+//                     up to 16 empty squares
+//     POST /validate  {"transcript": "f5 d6 c3", "notation": "standard"}
+//                     whether a game is legal, and how it stands at the end
+//
+// Positions are given as `position`, a position string like `--position`
+// takes, or as `moves` played from the server's starting position, or not
+// at all for the starting position. `depth` and `time_ms` are optional.
+// Squares are in standard notation, except transcripts in `notation`.
+// Errors come back as `{"error": "..."}` with a 4xx or 5xx status.
+//
+// Searches share one transposition table, so what one request works out
+// speeds up the next ones about the same game.
+
+// What the service lets one request have.
+pub struct Limits {
+    // Bytes of request body.
+    pub body: usize,
+    // Time searching, whatever the request asks for.
+    pub time: Duration,
+    // Requests worked on at once; the rest wait their turn.
+    pub threads: usize,
+}
+
+// Transposition table slots, about 40 MB worth.
+const TABLE_SLOTS: usize = 1 << 20;
+
+// How deep `/analyze` searches when not told.
+fn default_depth() -> u32 {
+    Engine::depths(MAX_LEVEL).0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Query {
+    position: Option<String>,
+    moves: Option<String>,
+    depth: Option<u32>,
+    time_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Game {
+    transcript: String,
+    notation: Option<Notation>,
+}
+
+// A request that can't be answered, and the status to say so with.
+struct Failure(u16, String);
+
+type Reply = Result<Value, Failure>;
+
+fn bad_request(text: impl Into<String>) -> Failure {
+    Failure(400, text.into())
+}
+
+// `othello http`: serves requests on `addr` until killed.
+pub fn serve(addr: &str, limits: Limits) -> Result<(), String> {
+    let server = Server::http(addr).map_err(|e| format!("failed to listen on {}: {}", addr, e))?;
+    println!(
+        "Serving the engine on http://{}, {} request(s) at a time.",
+        server.server_addr(),
+        limits.threads
+    );
+    run(server, limits);
+    Ok(())
+}
+
+// Answers requests on `limits.threads` threads, which share one table.
+pub fn run(server: Server, limits: Limits) {
+    let server = Arc::new(server);
+    let limits = Arc::new(limits);
+    let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+    let workers = (0..limits.threads.max(1))
+        .map(|_| {
+            let (server, limits, table) = (server.clone(), limits.clone(), table.clone());
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    handle(request, &limits, &table);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn handle(mut request: Request, limits: &Limits, table: &Arc<Transpositions>) {
+    let reply = read_body(&mut request, limits.body).and_then(|body| {
+        // Everything comes in the body, so a query string changes nothing.
+        let path = request.url().split('?').next().unwrap_or_default();
+        match (request.method(), path) {
+            (Method::Post, "/analyze") => analyze(&parse(&body)?, limits, table),
+            (Method::Post, "/solve") => solve(&parse(&body)?, limits, table),
+            (Method::Post, "/validate") => validate(&parse(&body)?),
+            (_, "/analyze" | "/solve" | "/validate") => Err(Failure(405, "use POST".to_string())),
+            (_, url) => Err(Failure(404, format!("no such endpoint `{}`", url))),
+        }
+    });
+    let (status, value) = match reply {
+        Ok(value) => (200, value),
+        Err(Failure(status, text)) => (status, json!({ "error": text })),
+    };
+    let json =
+        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("a valid header");
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(json);
+    // The client may have given up waiting; there is nobody to tell.
+    let _ = request.respond(response);
+}
+
+// Reads the body, as long as it is no longer than `limit`.
+fn read_body(request: &mut Request, limit: usize) -> Result<String, Failure> {
+    let too_large = || Failure(413, format!("the body is over {} bytes", limit));
+    if request.body_length().is_some_and(|length| length > limit) {
+        return Err(too_large());
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| bad_request(format!("failed to read the body: {}", e)))?;
+    if body.len() > limit {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, Failure> {
+    serde_json::from_str(body).map_err(|e| bad_request(format!("invalid request: {}", e)))
+}
+
+impl Query {
+    // The position asked about, which must still have moves left.
+    fn position(&self) -> Result<(Board, Turn), Failure> {
+        let unplayable = |e| Failure(422, e);
+        let over = || Failure(422, "the game is already over".to_string());
+        match (&self.position, &self.moves) {
+            (Some(_), Some(_)) => Err(bad_request("give `position` or `moves`, not both")),
+            (Some(position), None) => {
+                let setup = Setup::read(position).map_err(unplayable)?;
+                let board = &setup.board;
+                if !Move::has_moves(board, Turn::Black) && !Move::has_moves(board, Turn::White) {
+                    return Err(over());
+                }
+                setup.check().map_err(unplayable)?;
+                Ok((setup.board, setup.turn))
+            }
+            (None, moves) => {
+                let moves = moves.as_deref().unwrap_or("");
+                let moves = transcript::parse(moves, Notation::Standard).map_err(unplayable)?;
+                let last = transcript::replay(&moves)
+                    .map_err(unplayable)?
+                    .pop()
+                    .expect("starting position");
+                if last.is_over() {
+                    return Err(over());
+                }
+                Ok((last.board, last.turn))
+            }
+        }
+    }
+
+    // When the search has to stop, at the latest.
+    fn deadline(&self, limits: &Limits) -> Instant {
+        let asked = self.time_ms.map_or(limits.time, Duration::from_millis);
+        Instant::now() + asked.min(limits.time)
+    }
+}
+
+fn analyze(query: &Query, limits: &Limits, table: &Arc<Transpositions>) -> Reply {
+    let (board, turn) = query.position()?;
+    let start = Instant::now();
+    let depth = query.depth.unwrap_or_else(default_depth).max(1);
+    let depth = depth.min(engine::empties(&board) as u32);
+    let mut engine = Engine::with_table(Some(query.deadline(limits)), table.clone());
+    let scored = engine.analyze(&board, turn, depth);
+    if engine.depth == 0 && !scored.is_empty() {
+        return Err(Failure(
+            503,
+            "ran out of time before finishing depth 1".to_string(),
+        ));
+    }
+
+    let moves = scored
+        .iter()
+        .map(|&((row, col), score)| json!({"move": protocol::square(row, col), "score": score}))
+        .collect::<Vec<_>>();
+    let pv = match scored.first() {
+        Some(&(best, _)) => engine.pv(&board, turn, best, engine.depth as usize),
+        None => vec![Move::Pass],
+    };
+    Ok(json!({
+        "turn": protocol::side_name(turn),
+        "depth": engine.depth,
+        "complete": !engine.aborted(),
+        "moves": moves,
+        "pv": line(&pv),
+        "nodes": engine.nodes,
+        "time_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+fn solve(query: &Query, limits: &Limits, table: &Arc<Transpositions>) -> Reply {
+    let (board, turn) = query.position()?;
+    let empties = engine::empties(&board);
+    if empties > MAX_SOLVE_EMPTIES {
+        return Err(Failure(
+            422,
+            format!(
+                "{} empty squares is too many to solve (at most {})",
+                empties, MAX_SOLVE_EMPTIES
+            ),
+        ));
+    }
+
+    let start = Instant::now();
+    let mut engine = Engine::with_table(Some(query.deadline(limits)), table.clone());
+    let (best, score) = engine.solve(&board, turn);
+    if engine.aborted() {
+        return Err(Failure(
+            503,
+            "ran out of time before solving it".to_string(),
+        ));
+    }
+    let pv = match best {
+        Some(best) => engine.pv(&board, turn, best, empties * 2),
+        None => vec![Move::Pass],
+    };
+    Ok(json!({
+        "turn": protocol::side_name(turn),
+        "score": score,
+        "best": line(&pv[..1])[0],
+        "pv": line(&pv),
+        "nodes": engine.nodes,
+        "time_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+// Checks a whole game. A game that isn't legal is still a good request,
+// so it gets an answer saying what is wrong with it.
+fn validate(game: &Game) -> Reply {
+    let invalid = |e: String| Ok(json!({"valid": false, "error": e}));
+    let (start, text) = match setup::split(&game.transcript) {
+        Ok(split) => split,
+        Err(e) => return invalid(e),
+    };
+    let start = start.unwrap_or_else(|| setup::start().clone());
+    let moves = match transcript::parse(text, game.notation.unwrap_or(Notation::Standard)) {
+        Ok(moves) => moves,
+        Err(e) => return invalid(e),
+    };
+    let positions = match transcript::replay_from(&start, &moves) {
+        Ok(positions) => positions,
+        Err(e) => return invalid(e),
+    };
+
+    let last = positions.last().expect("starting position");
+    let (black, white) = last.board.count_discs();
+    let over = last.is_over();
+    let setup = Setup {
+        board: last.board.clone(),
+        turn: last.turn,
+    };
+    Ok(json!({
+        "valid": true,
+        "plies": positions.len() - 1,
+        "over": over,
+        "turn": if over { Value::Null } else { json!(protocol::side_name(last.turn)) },
+        "black": black,
+        "white": white,
+        "position": setup.format(),
+    }))
+}
+
+// Moves as strings, in standard notation.
+fn line(moves: &[Move]) -> Vec<String> {
+    moves
+        .iter()
+        .map(|mv| match mv {
+            Move::Play(row, col) => protocol::square(*row, *col),
+            _ => "pass".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpStream};
+
+    fn start(limits: Limits) -> SocketAddr {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        thread::spawn(move || run(server, limits));
+        addr
+    }
+
+    fn post(addr: SocketAddr, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            body: 1024,
+            time: Duration::from_secs(10),
+            threads: 2,
+        }
+    }
+
+    #[test]
+    fn analyzes_and_solves_positions() {
+        let addr = start(limits());
+        let (status, reply) = post(addr, "/analyze?level=5", r#"{"moves": "f5", "depth": 3}"#);
+        assert_eq!(status, 200);
+        assert_eq!(reply["turn"], "white");
+        assert_eq!(reply["depth"], 3);
+        assert_eq!(reply["moves"].as_array().unwrap().len(), 3);
+        assert_eq!(reply["pv"][0], reply["moves"][0]["move"]);
+        assert_eq!(reply["pv"].as_array().unwrap().len(), 3);
+
+        // A random game with eight empties left, as in the engine's tests.
+        let moves = "c4c5f6c3b5g7e3e6c2f3g3a5h8b3f4f2b4f5f7h3a3d2e2e1a6e7d7\
+                     c1c6g8f1g4d1b6b1d3g6b7f8a7c7h6a8b2g5g2a1d6h2h5a4d8";
+        let (status, reply) = post(addr, "/solve", &json!({ "moves": moves }).to_string());
+        assert_eq!(status, 200);
+        assert_eq!(reply["score"], 10);
+        assert_eq!(reply["best"], reply["pv"][0]);
+        let pv = reply["pv"].as_array().unwrap().iter();
+        let finished = pv.fold(moves.to_string(), |moves, mv| moves + mv.as_str().unwrap());
+
+        let (status, reply) = post(addr, "/solve", "{}");
+        assert_eq!(status, 422);
+        assert!(reply["error"].as_str().unwrap().contains("too many"));
+
+        // A finished game is turned away however it is given.
+        let full = format!("{} X", "X".repeat(64));
+        for body in [json!({ "moves": finished }), json!({ "position": full })] {
+            for path in ["/analyze", "/solve"] {
+                let (status, reply) = post(addr, path, &body.to_string());
+                assert_eq!(status, 422);
+                assert_eq!(reply["error"], "the game is already over");
+            }
+        }
+    }
+
+    #[test]
+    fn validates_transcripts() {
+        let addr = start(limits());
+        let (_, reply) = post(addr, "/validate", r#"{"transcript": "f5 d6 c3"}"#);
+        assert_eq!(reply["valid"], true);
+        assert_eq!(
+            (&reply["plies"], &reply["turn"]),
+            (&json!(3), &json!("white"))
+        );
+        assert_eq!((&reply["black"], &reply["white"]), (&json!(5), &json!(2)));
+
+        let (_, reply) = post(addr, "/validate", r#"{"transcript": "6e 4f"}"#);
+        assert_eq!(reply["valid"], false);
+        let (_, reply) = post(
+            addr,
+            "/validate",
+            r#"{"transcript": "6e 4f", "notation": "classic"}"#,
+        );
+        assert_eq!(reply["valid"], true);
+    }
+
+    #[test]
+    fn turns_away_requests_it_will_not_serve() {
+        let addr = start(Limits {
+            body: 32,
+            ..limits()
+        });
+        let (status, _) = post(
+            addr,
+            "/analyze",
+            &format!(r#"{{"moves": "{}"}}"#, "f5".repeat(20)),
+        );
+        assert_eq!(status, 413);
+        let (status, _) = post(addr, "/analyze", r#"{"depht": 3}"#);
+        assert_eq!(status, 400);
+        let (status, _) = post(addr, "/analyze", r#"{"moves": "a1"}"#);
+        assert_eq!(status, 422);
+        let (status, _) = post(addr, "/perft", "{}");
+        assert_eq!(status, 404);
+        let (status, _) = post(addr, "/perft?depth=3", "{}");
+        assert_eq!(status, 404);
+    }
+}
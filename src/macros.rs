@@ -1,5 +1,7 @@
 // This `flip` macro just flips discs when a disc is placed
 // at a valid cell.
+// A line of discs ends at anything but an opponent's disc, so empty and
+// `Cell::Blocked` squares both stop it like the edge of the board does.
 #[macro_export]
 macro_rules! flip {
     ( $board:expr, $you:expr, $opponent:expr, $dir:expr, $row:expr, $col:expr ) => {{
@@ -29,7 +31,7 @@ macro_rules! flip {
             Down => {
                 if $board.cells[$row + 1][$col] == $opponent {
                     let mut count = 2;
-                    while $row + count != 9 {
+                    while $row + count != $board.size + 1 {
                         if $board.cells[$row + count][$col] == $opponent {
                             count += 1;
                             continue;
@@ -71,7 +73,7 @@ macro_rules! flip {
             Right => {
                 if $board.cells[$row][$col + 1] == $opponent {
                     let mut count = 2;
-                    while $col + count != 9 {
+                    while $col + count != $board.size + 1 {
                         if $board.cells[$row][$col + count] == $opponent {
                             count += 1;
                             continue;
@@ -113,7 +115,7 @@ macro_rules! flip {
             UpRight => {
                 if $board.cells[$row - 1][$col + 1] == $opponent {
                     let mut count = 2;
-                    while $row - count != 0 && $col + count != 9 {
+                    while $row - count != 0 && $col + count != $board.size + 1 {
                         if $board.cells[$row - count][$col + count] == $opponent {
                             count += 1;
                             continue;
@@ -134,7 +136,7 @@ macro_rules! flip {
             DownLeft => {
                 if $board.cells[$row + 1][$col - 1] == $opponent {
                     let mut count = 2;
-                    while $row + count != 9 && $col - count != 0 {
+                    while $row + count != $board.size + 1 && $col - count != 0 {
                         if $board.cells[$row + count][$col - count] == $opponent {
                             count += 1;
                             continue;
@@ -155,7 +157,7 @@ macro_rules! flip {
             DownRight => {
                 if $board.cells[$row + 1][$col + 1] == $opponent {
                     let mut count = 2;
-                    while $row + count != 9 && $col + count != 9 {
+                    while $row + count != $board.size + 1 && $col + count != $board.size + 1 {
                         if $board.cells[$row + count][$col + count] == $opponent {
                             count += 1;
                             continue;
@@ -180,15 +182,15 @@ macro_rules! flip {
 // This macro handles several cases where:
 //   1. If cells are available for whichever player to play.
 //   2. Calculate scores when a game ends (automatically).
+// Lines stop at `Cell::Blocked` squares, the same as in `flip`.
 #[macro_export]
 macro_rules! check {
     ( $board:expr, $you:expr, $opponent:expr, $dir:expr ) => {{
-        use crate::board::SIZE;
         use Direction::*;
         match $dir {
             Up => {
-                for row in 3..SIZE {
-                    for col in 1..SIZE {
+                for row in 3..=$board.size {
+                    for col in 1..=$board.size {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row - 1][col] == $opponent {
                                 let mut count = 2;
@@ -209,12 +211,12 @@ macro_rules! check {
                 false
             }
             Down => {
-                for row in 1..=6 {
-                    for col in 1..SIZE {
+                for row in 1..=$board.size - 2 {
+                    for col in 1..=$board.size {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row + 1][col] == $opponent {
                                 let mut count = 2;
-                                while row + count != 9 {
+                                while row + count != $board.size + 1 {
                                     if $board.cells[row + count][col] == $opponent {
                                         count += 1;
                                         continue;
@@ -231,8 +233,8 @@ macro_rules! check {
                 false
             }
             Left => {
-                for row in 1..SIZE {
-                    for col in 3..SIZE {
+                for row in 1..=$board.size {
+                    for col in 3..=$board.size {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row][col - 1] == $opponent {
                                 let mut count = 2;
@@ -253,12 +255,12 @@ macro_rules! check {
                 false
             }
             Right => {
-                for row in 1..SIZE {
-                    for col in 1..=6 {
+                for row in 1..=$board.size {
+                    for col in 1..=$board.size - 2 {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row][col + 1] == $opponent {
                                 let mut count = 2;
-                                while col + count != 9 {
+                                while col + count != $board.size + 1 {
                                     if $board.cells[row][col + count] == $opponent {
                                         count += 1;
                                         continue;
@@ -275,8 +277,8 @@ macro_rules! check {
                 false
             }
             UpLeft => {
-                for row in 3..SIZE {
-                    for col in 3..SIZE {
+                for row in 3..=$board.size {
+                    for col in 3..=$board.size {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row - 1][col - 1] == $opponent {
                                 let mut count = 2;
@@ -297,12 +299,12 @@ macro_rules! check {
                 false
             }
             UpRight => {
-                for row in 3..SIZE {
-                    for col in 1..=6 {
+                for row in 3..=$board.size {
+                    for col in 1..=$board.size - 2 {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row - 1][col + 1] == $opponent {
                                 let mut count = 2;
-                                while row - count != 0 && col + count != 9 {
+                                while row - count != 0 && col + count != $board.size + 1 {
                                     if $board.cells[row - count][col + count] == $opponent {
                                         count += 1;
                                         continue;
@@ -319,12 +321,12 @@ macro_rules! check {
                 false
             }
             DownLeft => {
-                for row in 1..=6 {
-                    for col in 3..SIZE {
+                for row in 1..=$board.size - 2 {
+                    for col in 3..=$board.size {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row + 1][col - 1] == $opponent {
                                 let mut count = 2;
-                                while row + count != 9 && col - count != 0 {
+                                while row + count != $board.size + 1 && col - count != 0 {
                                     if $board.cells[row + count][col - count] == $opponent {
                                         count += 1;
                                         continue;
@@ -341,12 +343,14 @@ macro_rules! check {
                 false
             }
             DownRight => {
-                for row in 1..=6 {
-                    for col in 1..=6 {
+                for row in 1..=$board.size - 2 {
+                    for col in 1..=$board.size - 2 {
                         if $board.cells[row][col] == Cell::Okay {
                             if $board.cells[row + 1][col + 1] == $opponent {
                                 let mut count = 2;
-                                while row + count != 9 && col + count != 9 {
+                                while row + count != $board.size + 1
+                                    && col + count != $board.size + 1
+                                {
                                     if $board.cells[row + count][col + count] == $opponent {
                                         count += 1;
                                         continue;
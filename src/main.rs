@@ -1,10 +1,593 @@
+mod archive;
 mod board;
+mod client;
+mod clock;
+mod commands;
+mod config;
+mod engine;
+mod error;
 mod events;
 mod game;
+mod ggf;
+mod http;
 mod macros;
+mod perft;
+mod profiles;
+mod protocol;
+mod puzzle;
+mod review;
+mod server;
+mod setup;
+mod theme;
+mod transcript;
+mod tree;
+mod tui;
+mod viewer;
+mod websocket;
 
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use board::Variant;
+use client::Lobby;
+use clock::TimeControl;
+use config::Config;
+use events::{Move, Notation, Turn};
 use game::Game;
+use setup::{Holes, Setup, Start};
+
+#[derive(Parser)]
+#[command(
+    name = "othello",
+    version,
+    about = "Othello in the terminal, with an engine to play against and analyze with",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+    // `othello` on its own plays, so it takes the flags of `play` too.
+    #[command(flatten)]
+    play: PlayArgs,
+}
+
+#[derive(Args)]
+struct GlobalArgs {
+    /// Read settings from this file instead of the default config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Color theme: dark, light, high-contrast, monochrome or one of your own
+    #[arg(long, global = true, value_name = "NAME")]
+    theme: Option<String>,
+    /// Draw with plain ASCII instead of Unicode discs
+    #[arg(long, global = true)]
+    ascii: bool,
+    /// How squares are written: classic (3d) or standard (d3)
+    #[arg(long, global = true)]
+    notation: Option<Notation>,
+    /// Squares across the board, even and 4 to 16 (default 8)
+    #[arg(long, global = true, value_name = "N", value_parser = parse_size)]
+    size: Option<usize>,
+    /// Rules to play by: othello, or anti where the fewest discs win
+    #[arg(long, global = true)]
+    variant: Option<Variant>,
+    /// Where games start: standard, or random for one of the eight-disc starts
+    #[arg(long, global = true, value_name = "START", value_parser = parse_start)]
+    start: Option<Start>,
+    /// Start from a position: 64 squares of X, O or -, then X or O to move
+    #[arg(
+        long,
+        global = true,
+        value_name = "POSITION",
+        conflicts_with = "start",
+        allow_hyphen_values = true
+    )]
+    position: Option<String>,
+    /// Squares out of play: none, random, or a list like "c3 f6"
+    #[arg(long, global = true, value_name = "SQUARES")]
+    holes: Option<Holes>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a game (the default)
+    Play(PlayArgs),
+    /// Print every position of a game from its transcript
+    Replay {
+        /// Transcript or GGF record to read, or `-` for stdin
+        file: PathBuf,
+        /// Step through the game with the arrow keys, and play on from any move
+        #[arg(short, long)]
+        interactive: bool,
+    },
+    /// Score every legal move of a position
+    Analyze {
+        /// Moves leading to the position, e.g. f5d6c3 (default: the start)
+        #[arg(default_value = "")]
+        moves: String,
+        /// How many moves ahead to search
+        #[arg(long, default_value_t = 6)]
+        depth: u32,
+    },
+    /// Work out the exact result of a position near the end of the game
+    Solve {
+        /// Moves leading to the position (default: the start)
+        #[arg(default_value = "")]
+        moves: String,
+    },
+    /// Play AI players against each other
+    Match {
+        /// The first player, `ai` or `ai:<level>`
+        #[arg(long, default_value = "ai")]
+        black: String,
+        /// The second player, `ai` or `ai:<level>`
+        #[arg(long, default_value = "ai")]
+        white: String,
+        /// How many games to play; players swap colors every game
+        #[arg(long, default_value_t = 2)]
+        games: u32,
+        /// How many random moves each opening starts with
+        #[arg(long, default_value_t = 4, value_name = "N")]
+        opening: usize,
+    },
+    /// Go over a finished game move by move, pointing out the mistakes
+    Review {
+        /// Transcript to read, or `-` for stdin
+        file: PathBuf,
+        /// Check the moves with the search of an AI of this level
+        #[arg(long, default_value_t = review::DEFAULT_LEVEL, value_parser = parse_level)]
+        level: u8,
+    },
+    /// Go through a game and its variations, with comments and evaluations
+    Edit {
+        /// Tree or transcript to edit; a new tree is saved here
+        file: PathBuf,
+    },
+    /// Rewrite a transcript from one notation into another
+    Convert {
+        /// Transcript to read, or `-` for stdin (the default)
+        file: Option<PathBuf>,
+        #[arg(long)]
+        from: Notation,
+        #[arg(long)]
+        to: Notation,
+    },
+    /// Count the positions reachable from the start, for testing move generation
+    Perft { depth: u32 },
+    /// Host games for players to join over the network
+    Serve {
+        /// Address to listen on; use 0.0.0.0:<port> to take players from other machines
+        #[arg(long, default_value = "127.0.0.1:7878", value_name = "ADDR")]
+        listen: String,
+        /// How long a player who loses the connection has to come back
+        #[arg(long, default_value_t = 60, value_name = "SECONDS")]
+        reconnect: u64,
+        /// Also take browsers and other WebSocket clients, speaking JSON, on this address
+        #[arg(long, value_name = "ADDR")]
+        websocket: Option<String>,
+        /// Time control for every game, e.g. 5+3 (minutes + increment seconds)
+        #[arg(long, value_name = "SPEC", value_parser = parse_clock)]
+        clock: Option<ClockArg>,
+    },
+    /// Serve the engine over HTTP, for other programs to analyze and check games with
+    Http {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+        listen: String,
+        /// How many requests to work on at once
+        #[arg(long, default_value_t = 4, value_name = "N")]
+        threads: usize,
+        /// The largest request body to accept
+        #[arg(long, default_value_t = 64 * 1024, value_name = "BYTES")]
+        max_body: usize,
+        /// The longest any one search may take
+        #[arg(long, default_value_t = 30, value_name = "SECONDS")]
+        max_time: u64,
+    },
+    /// Show the rating leaderboards, or one player's record
+    Stats {
+        /// A player's name, or `ai:<level>` for an AI
+        player: Option<String>,
+    },
+    /// Find the only winning move of positions near the end of a game
+    Puzzle {
+        /// Who the puzzles count towards (default: the black player's name)
+        #[arg(long)]
+        player: Option<String>,
+        /// Only serve new puzzles found with the solver, not the bundled ones
+        #[arg(long)]
+        generate: bool,
+        /// How many empty squares new puzzles have
+        #[arg(long, default_value_t = 10, value_parser = parse_empties)]
+        empties: usize,
+        /// Show everyone's puzzle ratings and streaks instead
+        #[arg(long, conflicts_with_all = ["player", "generate"])]
+        scores: bool,
+    },
+    /// Look up finished games in the archive
+    Games {
+        /// Games played by this player, on either side
+        #[arg(long, value_name = "NAME")]
+        player: Option<String>,
+        /// Games won by `black`, `white` or a player, or `draw`n
+        #[arg(long, value_name = "RESULT")]
+        winner: Option<String>,
+        /// Games played on or after this day, YYYY-MM-DD
+        #[arg(long, value_name = "DATE", value_parser = archive::parse_date)]
+        since: Option<u64>,
+        /// Games played on or before this day, YYYY-MM-DD
+        #[arg(long, value_name = "DATE", value_parser = archive::parse_date)]
+        until: Option<u64>,
+        /// Games that opened with these moves, or the same moves mirrored or rotated
+        #[arg(long, value_name = "MOVES")]
+        opening: Option<String>,
+        /// Games that went through this position string, any way round the board
+        #[arg(long, value_name = "POSITION", allow_hyphen_values = true)]
+        reached: Option<String>,
+        /// Print one game, for `replay` or `play --load`
+        #[arg(long, value_name = "ID", conflicts_with_all = ["player", "winner", "since", "until", "opening", "reached"])]
+        show: Option<u32>,
+    },
+    /// Play or watch a game hosted with `othello serve`
+    Connect {
+        /// Where the server is, e.g. 127.0.0.1:7878
+        addr: String,
+        /// The name to play under (default: your user name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Take back a seat after losing the connection
+        #[arg(long)]
+        token: Option<String>,
+        /// Open a game and wait for someone to join, playing black or white
+        #[arg(
+            long,
+            value_name = "SIDE",
+            num_args = 0..=1,
+            default_missing_value = "black",
+            value_parser = protocol::parse_side,
+            conflicts_with_all = ["join", "watch"]
+        )]
+        seek: Option<Turn>,
+        /// Join the open game with this number
+        #[arg(long, value_name = "ID", conflicts_with = "watch")]
+        join: Option<u32>,
+        /// Watch the game with this number
+        #[arg(long, value_name = "ID")]
+        watch: Option<u32>,
+    },
+}
+
+#[derive(Args, Default)]
+struct PlayArgs {
+    /// Who plays black: human, ai or ai:<level> (1 to 10)
+    #[arg(long, value_name = "PLAYER")]
+    black: Option<String>,
+    /// Who plays white: human, ai or ai:<level> (1 to 10)
+    #[arg(long, value_name = "PLAYER")]
+    white: Option<String>,
+    /// Play in a full-screen interface, with the mouse or cursor keys
+    #[arg(long)]
+    tui: bool,
+    /// Point out the legal moves
+    #[arg(long, overrides_with = "no_hints")]
+    hints: bool,
+    /// Don't point out the legal moves
+    #[arg(long)]
+    no_hints: bool,
+    /// Time control, <minutes>+<increment> or <minutes>d<delay>, or `none`
+    #[arg(long, value_name = "SPEC", value_parser = parse_clock)]
+    clock: Option<ClockArg>,
+    /// Pick up a game saved with `q`, or from any transcript
+    #[arg(long, value_name = "FILE")]
+    load: Option<PathBuf>,
+    /// Set up the starting position before playing
+    #[arg(long, conflicts_with = "load")]
+    setup: bool,
+}
+
+#[derive(Clone)]
+struct ClockArg(Option<TimeControl>);
+
+fn parse_clock(spec: &str) -> Result<ClockArg, String> {
+    match spec {
+        "none" => Ok(ClockArg(None)),
+        _ => spec.parse().map(|control| ClockArg(Some(control))),
+    }
+}
+
+fn parse_level(level: &str) -> Result<u8, String> {
+    let level = level
+        .parse()
+        .map_err(|_| format!("invalid AI level `{}`", level))?;
+    config::check_level(level)
+}
+
+fn parse_empties(empties: &str) -> Result<usize, String> {
+    let range = puzzle::MIN_EMPTIES..=puzzle::MAX_EMPTIES;
+    match empties.parse() {
+        Ok(empties) if range.contains(&empties) => Ok(empties),
+        _ => Err(format!(
+            "`{}` is not a number of empties from {} to {}",
+            empties,
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size
+        .parse()
+        .map_err(|_| format!("invalid board size `{}`", size))?;
+    board::check_size(size)
+}
+
+// Positions are given with `--position` instead.
+fn parse_start(start: &str) -> Result<Start, String> {
+    match start {
+        "standard" | "random" => start.parse(),
+        _ => Err(format!(
+            "invalid start `{}` (expected `standard` or `random`)",
+            start
+        )),
+    }
+}
 
 fn main() {
-    Game::start();
+    let cli = Cli::parse();
+    let global = cli.global;
+    let mut config = Config::load(global.config.as_deref()).unwrap_or_else(|e| exit_with(&e));
+    config.ascii |= global.ascii;
+    config.theme = global.theme.or(config.theme);
+    config.notation = global.notation.unwrap_or(config.notation);
+    // A position says how big its board is, unless told otherwise.
+    let position_size = global.position.as_deref().and_then(setup::size_of);
+    config.size = global.size.or(position_size).unwrap_or(config.size);
+    config.variant = global.variant.unwrap_or(config.variant);
+    if let Some(start) = global.start {
+        config.start = start;
+    }
+    if let Some(holes) = global.holes {
+        config.holes = holes;
+    }
+    if let Some(position) = global.position {
+        config.start = Start::Position(position);
+    }
+
+    let command = cli.command.unwrap_or(Command::Play(cli.play));
+    if let Command::Play(play) = &command {
+        play.apply(&mut config).unwrap_or_else(|e| exit_with(&e));
+    }
+    // A transcript being converted is written in the notation it comes from.
+    if let Command::Convert { from, .. } = &command {
+        config.notation = *from;
+    }
+
+    if let Err(e) = theme::init(config.theme.as_deref(), config.ascii) {
+        exit_with(&e);
+    }
+    let (tui, clock, ai_level) = (config.tui, config.clock, config.ai_level);
+    config::init(config);
+    // A game being picked up, set up, replayed or edited decides its own
+    // start.
+    if !matches!(
+        command,
+        Command::Play(_) | Command::Replay { .. } | Command::Edit { .. }
+    ) {
+        let config = config::current();
+        let start =
+            Setup::new(&config.start, &config.holes, config.size).unwrap_or_else(|e| exit_with(&e));
+        setup::init(start);
+    }
+
+    let result = match command {
+        Command::Play(play) => play.moves().and_then(|moves| match moves {
+            None => Ok(()),
+            Some(moves) if tui => {
+                tui::start(clock, &moves).map_err(|e| format!("terminal error: {}", e))
+            }
+            Some(moves) => Game::start(clock, &moves),
+        }),
+        Command::Replay { file, interactive } => {
+            commands::read_input(Some(&file)).and_then(|text| commands::replay(&text, interactive))
+        }
+        Command::Review { file, level } => {
+            commands::read_input(Some(&file)).and_then(|text| review::run(&text, level))
+        }
+        Command::Edit { file } => tree::run(&file),
+        Command::Analyze { moves, depth } => commands::analyze(&moves, depth),
+        Command::Solve { moves } => commands::solve(&moves),
+        Command::Match {
+            black,
+            white,
+            games,
+            opening,
+        } => match_players(&black, &white, ai_level)
+            .and_then(|players| commands::play_match(players, games, opening)),
+        Command::Convert { file, from, to } => commands::read_input(file.as_deref())
+            .and_then(|text| commands::convert(&text, from, to))
+            .map(|converted| println!("{}", converted)),
+        Command::Perft { depth } => {
+            perft::run(depth);
+            Ok(())
+        }
+        Command::Serve {
+            listen,
+            reconnect,
+            websocket,
+            clock,
+        } => server::serve(
+            &listen,
+            websocket.as_deref(),
+            Duration::from_secs(reconnect),
+            clock.and_then(|clock| clock.0),
+        ),
+        Command::Http {
+            listen,
+            threads,
+            max_body,
+            max_time,
+        } => http::serve(
+            &listen,
+            http::Limits {
+                body: max_body,
+                time: Duration::from_secs(max_time),
+                threads,
+            },
+        ),
+        Command::Stats { player } => profiles::stats(player.as_deref()),
+        Command::Puzzle { scores: true, .. } => puzzle::scores(),
+        Command::Puzzle {
+            player,
+            generate,
+            empties,
+            ..
+        } => {
+            let player = player.unwrap_or_else(|| config::current().name(Turn::Black).to_string());
+            puzzle::run(&player, generate, empties)
+        }
+        Command::Games { show: Some(id), .. } => archive::show(id),
+        Command::Games {
+            player,
+            winner,
+            since,
+            until,
+            opening,
+            reached,
+            show: None,
+        } => games_query(player, winner, since, until, opening, reached)
+            .and_then(|query| archive::list(&query)),
+        Command::Connect {
+            addr,
+            name,
+            token,
+            seek,
+            join,
+            watch,
+        } => {
+            let name = name.unwrap_or_else(client::default_name);
+            let lobby = match (seek, join, watch) {
+                (Some(side), _, _) => Lobby::Seek(side),
+                (_, Some(id), _) => Lobby::Join(id),
+                (_, _, Some(id)) => Lobby::Watch(id),
+                _ => Lobby::Any,
+            };
+            client::connect(&addr, &name, token, lobby)
+        }
+    };
+    if let Err(e) = result {
+        exit_with(&e);
+    }
+}
+
+impl PlayArgs {
+    // Layers the flags over whatever the config file said.
+    fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(black) = &self.black {
+            config.set_player(Turn::Black, black)?;
+        }
+        if let Some(white) = &self.white {
+            config.set_player(Turn::White, white)?;
+        }
+        config.tui |= self.tui;
+        if self.hints {
+            config.hints = true;
+        }
+        if self.no_hints {
+            config.hints = false;
+        }
+        if let Some(ClockArg(clock)) = &self.clock {
+            config.clock = *clock;
+        }
+        Ok(())
+    }
+
+    // Settles where the game starts, and returns the moves of the game
+    // being picked up, if any. `None` if the player left the setup editor.
+    fn moves(&self) -> Result<Option<Vec<Move>>, String> {
+        let config = config::current();
+        let path = match &self.load {
+            Some(path) => path,
+            None => {
+                let mut start = Setup::new(&config.start, &config.holes, config.size)?;
+                if self.setup {
+                    match setup::edit(start).map_err(|e| e.to_string())? {
+                        Some(edited) => start = edited,
+                        None => return Ok(None),
+                    }
+                }
+                setup::init(start);
+                return Ok(Some(Vec::new()));
+            }
+        };
+        let text = commands::read_input(Some(path))?;
+        let (start, text) = setup::split(&text)?;
+        let start = match start {
+            Some(start) => start,
+            None => Setup::new(&config.start, &config.holes, config.size)?,
+        };
+        start.fits(config.size)?;
+        setup::init(start);
+
+        let moves = transcript::parse(text, config.notation)?;
+        let position = transcript::replay(&moves)?
+            .pop()
+            .expect("starting position");
+        if position.is_over() {
+            return Err(format!("the game in {} is already over", path.display()));
+        }
+        Ok(Some(moves))
+    }
+}
+
+// The two sides of a match, named after how they were given, with the
+// AI level each plays at.
+fn match_players(black: &str, white: &str, default_level: u8) -> Result<[(String, u8); 2], String> {
+    let level = |spec| match config::parse_player(spec, default_level)? {
+        Some(level) => Ok(level),
+        None => Err("a match is played between AI players".to_string()),
+    };
+    // Two of the same player still need telling apart.
+    let (first, second) = if black == white {
+        (format!("{} #1", black), format!("{} #2", white))
+    } else {
+        (black.to_string(), white.to_string())
+    };
+    Ok([(first, level(black)?), (second, level(white)?)])
+}
+
+fn games_query(
+    player: Option<String>,
+    winner: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    opening: Option<String>,
+    reached: Option<String>,
+) -> Result<archive::Query, String> {
+    let mut query = archive::Query {
+        player,
+        winner,
+        since: since.map(|day| day * archive::SECONDS_PER_DAY),
+        // Up to the end of the day.
+        until: until.map(|day| (day + 1) * archive::SECONDS_PER_DAY),
+        ..archive::Query::default()
+    };
+    if let Some(moves) = opening {
+        query.opening(&transcript::parse(&moves, config::current().notation)?)?;
+    }
+    if let Some(position) = reached {
+        let setup = Setup::read(&position)?;
+        query.reached = Some(archive::key(&setup.board, setup.turn));
+    }
+    Ok(query)
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
 }
@@ -0,0 +1,139 @@
+use std::time::Instant;
+
+use crate::board::Board;
+use crate::events::{Move, PlayabilityChecker, Turn};
+
+// Counts the leaf nodes of the game tree `depth` plies below `board`.
+//
+// Passes are counted as a ply of their own, which is the usual convention
+// for Othello perft. A position where neither player can move is a leaf,
+// however much depth is left.
+pub fn perft(board: &Board, turn: Turn, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = Move::legal_moves(board, turn);
+    let moved = !moves.is_empty();
+    let mut nodes = 0;
+    for (row, col) in moves {
+        let next = Move::after(board, turn, row, col).expect("legal move");
+        nodes += perft(&next, turn.opponent(), depth - 1);
+    }
+
+    // `check_playablity` asks whether the *other* player can move, and it is
+    // driven by `check!` rather than `flip!`, so the two must agree here.
+    debug_assert_eq!(moved, Move::check_playablity(board, turn.opponent()));
+
+    if moved {
+        nodes
+    } else if Move::check_playablity(board, turn) {
+        perft(board, turn.opponent(), depth - 1)
+    } else {
+        1
+    }
+}
+
+// Entry point for `othello perft <depth>`.
+// Prints the leaf count of every depth up to the requested one.
+pub fn run(depth: u32) {
+    let board = Board::new();
+    for d in 1..=depth {
+        let start = Instant::now();
+        let nodes = perft(&board, Turn::Black, d);
+        println!(
+            "perft({}) = {} ({:.3}s)",
+            d,
+            nodes,
+            start.elapsed().as_secs_f64()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Cell;
+
+    // Known leaf counts from the standard start position, Black to move.
+    const START_PERFT: [u64; 8] = [4, 12, 56, 244, 1396, 8200, 55092, 390216];
+
+    #[test]
+    fn perft_depth_zero_is_one() {
+        assert_eq!(perft(&Board::new(), Turn::Black, 0), 1);
+    }
+
+    #[test]
+    fn perft_start_position() {
+        let board = Board::new();
+        for (i, &expected) in START_PERFT.iter().enumerate().take(7) {
+            let depth = i as u32 + 1;
+            assert_eq!(
+                perft(&board, Turn::Black, depth),
+                expected,
+                "depth {}",
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn perft_other_sizes() {
+        // 6x6 follows 8x8 until the edges come into reach.
+        let six = [4, 12, 56, 244, 1364, 7604];
+        // 4x4 is small enough to run out of moves along the way.
+        let four = [4, 12, 44, 128, 424, 1256, 3624, 9116, 20044, 36540];
+        for (size, counts) in [(6, &six[..]), (4, &four[..])] {
+            let board = Board::with_size(size);
+            for (i, &expected) in counts.iter().enumerate() {
+                let depth = i as u32 + 1;
+                assert_eq!(
+                    perft(&board, Turn::Black, depth),
+                    expected,
+                    "{}x{} depth {}",
+                    size,
+                    size,
+                    depth
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow in debug builds; run with `cargo test --release -- --ignored`.
+    fn perft_start_position_deep() {
+        assert_eq!(perft(&Board::new(), Turn::Black, 8), START_PERFT[7]);
+    }
+
+    #[test]
+    fn perft_counts_pass_as_ply() {
+        // Black to move on a board where only White can play:
+        //   A1 = White, A2 = Black, everything else empty.
+        // Black has no move, so the only line is a pass followed by
+        // White's single capture at A3.
+        let mut board = Board::new();
+        for row in 1..=board.size {
+            for col in 1..=board.size {
+                board.cells[row][col] = Cell::Illegal;
+            }
+        }
+        board.cells[1][1] = Cell::White;
+        board.cells[1][2] = Cell::Black;
+        board.validate_cells();
+
+        assert_eq!(perft(&board, Turn::Black, 1), 1);
+        assert_eq!(perft(&board, Turn::Black, 2), 1);
+    }
+
+    #[test]
+    fn perft_stops_at_game_over() {
+        // A full board has no moves for anyone, so it is a single leaf.
+        let mut board = Board::new();
+        for row in 1..=board.size {
+            for col in 1..=board.size {
+                board.cells[row][col] = Cell::Black;
+            }
+        }
+        assert_eq!(perft(&board, Turn::White, 5), 1);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::board::Board;
+use crate::config::{self, Player};
+use crate::events::{Move, Turn};
+use crate::protocol::{self, End, Outcome};
+
+// Everyone who has finished a game on this machine, with their results and
+// an Elo rating, kept in `profiles.json` next to the config file.
+//
+// Humans go by the name they play under, and AI players by their level as
+// `ai:<level>`, so people and every strength of the engine share one scale.
+
+// Where a new player's rating starts.
+pub const START_RATING: f64 = 1500.0;
+// The most one game can move a rating.
+const K: f64 = 32.0;
+// How many games `othello stats <name>` lists.
+const RECENT: usize = 10;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Profiles {
+    pub players: BTreeMap<String, Profile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Profile {
+    pub rating: f64,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    // Oldest first.
+    pub games: Vec<Record>,
+}
+
+// One finished game, from the side of the player it belongs to.
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    pub opponent: String,
+    pub side: String,
+    pub result: Score,
+    // `finished`, `resigned` or `time`.
+    pub end: String,
+    pub black: u16,
+    pub white: u16,
+    // The rating after the game.
+    pub rating: f64,
+    // Seconds since the Unix epoch.
+    pub time: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Score {
+    Win,
+    Loss,
+    Draw,
+}
+
+// Where a rating ended up after a game, and by how much it moved.
+#[derive(Clone, Copy)]
+pub struct Change {
+    pub rating: f64,
+    pub delta: f64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            rating: START_RATING,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            games: Vec::new(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn played(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+}
+
+impl Score {
+    fn points(self) -> f64 {
+        match self {
+            Score::Win => 1.0,
+            Score::Loss => 0.0,
+            Score::Draw => 0.5,
+        }
+    }
+
+    fn of(outcome: &Outcome, side: Turn) -> Score {
+        if outcome.winner == side {
+            Score::Win
+        } else if outcome.winner == Turn::Neither {
+            Score::Draw
+        } else {
+            Score::Loss
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0} ({:+.0})", self.rating, self.delta)
+    }
+}
+
+// The name a player's profile is kept under.
+pub fn key(player: &Player) -> String {
+    match player.ai {
+        Some(level) => ai(level),
+        None => player.name.clone(),
+    }
+}
+
+pub fn ai(level: u8) -> String {
+    format!("ai:{}", level)
+}
+
+// Whether a profile belongs to an AI player rather than a person.
+pub fn is_ai(key: &str) -> bool {
+    key.strip_prefix("ai:")
+        .is_some_and(|level| level.parse::<u8>().is_ok())
+}
+
+// Where profiles are kept, in the config directory.
+pub const FILE: &str = "profiles.json";
+
+pub fn path() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join(FILE))
+}
+
+// The chance of a player rated `rating` beating one rated `opponent`.
+pub fn expected(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// How a game ended on the move `mv`, made by `turn`, or `None` if it
+// didn't end there.
+pub fn outcome(board: &Board, turn: Turn, mv: Move) -> Option<Outcome> {
+    let (black, white) = board.count_discs();
+    let (end, winner) = match mv {
+        Move::Win(b, w) => (End::Finished, config::current().variant.winner(b, w)),
+        Move::Resign => (End::Resigned, turn.opponent()),
+        Move::Timeout => (End::Time, turn.opponent()),
+        _ => return None,
+    };
+    Some(Outcome {
+        end,
+        winner,
+        black,
+        white,
+    })
+}
+
+impl Profiles {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        read_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, self)
+    }
+
+    // Counts a finished game between `black` and `white` and moves both
+    // ratings, returning the changes as `[black, white]`.
+    pub fn record(&mut self, black: &str, white: &str, outcome: &Outcome) -> [Change; 2] {
+        let ratings = [self.rating(black), self.rating(white)];
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut changes = [Change {
+            rating: 0.0,
+            delta: 0.0,
+        }; 2];
+        let sides = [(black, white, Turn::Black), (white, black, Turn::White)];
+        for (i, &(name, opponent, side)) in sides.iter().enumerate() {
+            let score = Score::of(outcome, side);
+            let delta = K * (score.points() - expected(ratings[i], ratings[1 - i]));
+            let profile = self.players.entry(name.to_string()).or_default();
+            profile.rating += delta;
+            match score {
+                Score::Win => profile.wins += 1,
+                Score::Loss => profile.losses += 1,
+                Score::Draw => profile.draws += 1,
+            }
+            profile.games.push(Record {
+                opponent: opponent.to_string(),
+                side: protocol::side_name(side).to_string(),
+                result: score,
+                end: outcome.end.name().to_string(),
+                black: outcome.black,
+                white: outcome.white,
+                rating: profile.rating,
+                time,
+            });
+            changes[i] = Change {
+                rating: profile.rating,
+                delta,
+            };
+        }
+        changes
+    }
+
+    pub fn rating(&self, name: &str) -> f64 {
+        self.players.get(name).map_or(START_RATING, |p| p.rating)
+    }
+
+    // Players with at least one game, best first; AIs or people.
+    pub fn leaderboard(&self, ai: bool) -> Vec<(&str, &Profile)> {
+        let mut board: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(name, profile)| is_ai(name) == ai && profile.played() > 0)
+            .map(|(name, profile)| (name.as_str(), profile))
+            .collect();
+        board.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating).then(a.0.cmp(b.0)));
+        board
+    }
+}
+
+// Reads a JSON file kept next to the config file. One that isn't there
+// yet reads as empty.
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let write = || -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(value).expect("plain data serializes");
+        // Written aside and moved into place, so a crash can't leave half a file.
+        let partial = path.with_extension("json.tmp");
+        fs::write(&partial, text + "\n")?;
+        fs::rename(&partial, path)
+    };
+    write().map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Records a finished game in the profiles on disk, and describes how the
+// ratings moved. A player can't be rated against themselves, so a game
+// between two of the same player isn't counted.
+pub fn record_game(black: &str, white: &str, outcome: &Outcome) -> Result<Option<String>, String> {
+    let path = match path() {
+        Some(path) if black != white => path,
+        _ => return Ok(None),
+    };
+    let mut profiles = Profiles::load(&path)?;
+    let [b, w] = profiles.record(black, white, outcome);
+    profiles.save(&path)?;
+    Ok(Some(format!("Ratings: {} {}, {} {}", black, b, white, w)))
+}
+
+// `othello stats`: the leaderboards, or one player's record.
+pub fn stats(name: Option<&str>) -> Result<(), String> {
+    let path = path().ok_or("no home directory to keep profiles in")?;
+    let profiles = Profiles::load(&path)?;
+    match name {
+        Some(name) => match profiles.players.get(name) {
+            Some(profile) => print_profile(name, profile),
+            None => return Err(format!("no games recorded for {}", name)),
+        },
+        None if profiles.players.is_empty() => println!("No games recorded yet."),
+        None => {
+            print_leaderboard("Players", &profiles.leaderboard(false));
+            print_leaderboard("AI levels", &profiles.leaderboard(true));
+        }
+    }
+    Ok(())
+}
+
+fn print_leaderboard(title: &str, players: &[(&str, &Profile)]) {
+    if players.is_empty() {
+        return;
+    }
+    let width = players
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!("{}", title);
+    println!(
+        "  {:>3}  {:<width$}  {:>6}  {:>5}  {:>4}  {:>4}  {:>5}",
+        "#",
+        "Name",
+        "Rating",
+        "Games",
+        "Won",
+        "Lost",
+        "Drawn",
+        width = width
+    );
+    for (rank, (name, profile)) in players.iter().enumerate() {
+        println!(
+            "  {:>3}  {:<width$}  {:>6.0}  {:>5}  {:>4}  {:>4}  {:>5}",
+            rank + 1,
+            name,
+            profile.rating,
+            profile.played(),
+            profile.wins,
+            profile.losses,
+            profile.draws,
+            width = width
+        );
+    }
+    println!();
+}
+
+fn print_profile(name: &str, profile: &Profile) {
+    println!(
+        "{}: rated {:.0}, {} games, {} won, {} lost, {} drawn",
+        name,
+        profile.rating,
+        profile.played(),
+        profile.wins,
+        profile.losses,
+        profile.draws
+    );
+    println!("\nRecent games");
+    for record in profile.games.iter().rev().take(RECENT) {
+        let result = match record.result {
+            Score::Win => "won ",
+            Score::Loss => "lost",
+            Score::Draw => "drew",
+        };
+        let how = match End::parse(&record.end) {
+            Some(End::Resigned) => " by resignation",
+            Some(End::Time) => " on time",
+            Some(End::Forfeit) => " by forfeit",
+            _ => "",
+        };
+        println!(
+            "  {} as {} against {}, {}-{}{}  ({:.0})",
+            result, record.side, record.opponent, record.black, record.white, how, record.rating
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(winner: Turn) -> Outcome {
+        Outcome {
+            end: End::Finished,
+            winner,
+            black: 40,
+            white: 24,
+        }
+    }
+
+    #[test]
+    fn rates_humans_and_ai_levels_on_one_scale() {
+        let mut profiles = Profiles::default();
+        let [alice, ai] = profiles.record("Alice", "ai:3", &game(Turn::Black));
+        // Evenly matched, so the winner takes half of what's at stake.
+        assert_eq!(alice.delta, K / 2.0);
+        assert_eq!(ai.delta, -K / 2.0);
+
+        // Beating a weaker player earns less than losing to them costs.
+        let [alice, _] = profiles.record("Alice", "ai:3", &game(Turn::Black));
+        assert!(alice.delta > 0.0 && alice.delta < K / 2.0);
+        let [_, ai] = profiles.record("Alice", "ai:3", &game(Turn::White));
+        assert!(ai.delta > K / 2.0);
+        profiles.record("ai:3", "Alice", &game(Turn::Neither));
+
+        let alice = &profiles.players["Alice"];
+        assert_eq!((alice.wins, alice.losses, alice.draws), (2, 1, 1));
+        assert_eq!(alice.games.len(), 4);
+        assert_eq!(alice.games[3].side, "white");
+        assert_eq!(alice.games[3].result, Score::Draw);
+        // Ratings only move between the players, never in or out.
+        let total = profiles.rating("Alice") + profiles.rating("ai:3");
+        assert!((total - 2.0 * START_RATING).abs() < 1e-9);
+
+        let people = profiles.leaderboard(false);
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].0, "Alice");
+        assert_eq!(profiles.leaderboard(true)[0].0, "ai:3");
+    }
+
+    #[test]
+    fn keeps_profiles_on_disk() {
+        let dir = std::env::temp_dir().join(format!("othello-profiles-{}", std::process::id()));
+        let path = dir.join("profiles.json");
+        assert!(Profiles::load(&path).unwrap().players.is_empty());
+
+        let mut profiles = Profiles::default();
+        profiles.record("Bob", "ai:5", &game(Turn::White));
+        profiles.save(&path).unwrap();
+        let loaded = Profiles::load(&path).unwrap();
+        assert_eq!(loaded.players["Bob"].losses, 1);
+        assert_eq!(loaded.rating("ai:5"), profiles.rating("ai:5"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::MAX_SIZE;
+use crate::events::{Move, Notation, Turn};
+
+// What `othello serve` and its clients say to each other, one line per
+// message. Every line starts with a keyword, and squares are always
+// written in standard notation (`d3`), whatever the players see. Bots can
+// talk it over a plain TCP connection, e.g. with `nc localhost 7878`.
+//
+// Client to server:
+//
+//     hello <name> [<token>]   log in, or take your seats back with your token
+//     list                     ask for the open seeks and the games being played
+//     seek [<side>]            open a game and wait for an opponent (black by default)
+//     join <id>                take the open seat of a seek
+//     watch <id>               follow a game as a spectator
+//     leave                    stop watching, or take back a seek
+//     say <text>               chat to your game, or to the lobby outside of one
+//     moves                    ask for the legal moves of the side to move
+//     move <square>            play a disc
+//     pass                     pass, while the opponent has a move
+//     resign                   give up the game
+//
+// Server to client:
+//
+//     welcome <name> <token>   logged in; keep <token> to reconnect
+//     open <id> <name> <side>  a seek by <name>, who plays <side>
+//     playing <id> <black> <white>
+//                              a game being played
+//     listed                   the end of the answer to `list`
+//     closed <id>              a seek was taken or taken back
+//     game <id> <side>         you are in game <id>, as black, white or spectator
+//     waiting                  for an opponent to join
+//     players <black> <white>  who is playing
+//     board <position>         the whole board, as a position string
+//     played <side> <move>     a square, or `pass`
+//     skip <side>              <side> has no move and is skipped
+//     turn <side>              <side> is to move
+//     moves [<square>...]      the legal moves of the side to move
+//     clock <black> <white>    milliseconds left on each clock, in games with one
+//     away <side> <seconds>    <side> lost its connection, and has that long to come back
+//     back <side>              <side> is back
+//     over <how> <winner> <black discs> <white discs>
+//                              how: finished, resigned, forfeit or time;
+//                              winner: black, white or draw
+//     chat <name> <text>       someone said something
+//     error <text>             the last line was not accepted
+//
+// Sides are `black` or `white`. Logged-in players outside of a game hear
+// about new seeks with `open` and `closed` as they come and go.
+//
+// The same messages go over WebSocket as JSON; see `src/websocket.rs`.
+
+pub enum ClientMsg {
+    Hello { name: String, token: Option<String> },
+    List,
+    Seek(Turn),
+    Join(u32),
+    Watch(u32),
+    Leave,
+    Say(String),
+    Moves,
+    Move(usize, usize),
+    Pass,
+    Resign,
+}
+
+#[derive(Clone)]
+pub enum ServerMsg {
+    Welcome {
+        name: String,
+        token: String,
+    },
+    Open {
+        id: u32,
+        name: String,
+        side: Turn,
+    },
+    Playing {
+        id: u32,
+        black: String,
+        white: String,
+    },
+    Listed,
+    Closed(u32),
+    // `Turn::Neither` for a spectator.
+    Game {
+        id: u32,
+        side: Turn,
+    },
+    Waiting,
+    Players {
+        black: String,
+        white: String,
+    },
+    Board(String),
+    Played {
+        side: Turn,
+        mv: Move,
+    },
+    Skip(Turn),
+    Turn(Turn),
+    Moves(Vec<(usize, usize)>),
+    // Milliseconds left for black and white.
+    Clock {
+        black: u64,
+        white: u64,
+    },
+    Away {
+        side: Turn,
+        seconds: u64,
+    },
+    Back(Turn),
+    Over(Outcome),
+    Chat {
+        name: String,
+        text: String,
+    },
+    Error(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum End {
+    Finished,
+    Resigned,
+    Forfeit,
+    Time,
+}
+
+// How a game ended. A resignation, forfeit or loss on time is won by the
+// other side.
+#[derive(Clone, Copy)]
+pub struct Outcome {
+    pub end: End,
+    pub winner: Turn,
+    pub black: u16,
+    pub white: u16,
+}
+
+impl fmt::Display for ClientMsg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientMsg::Hello { name, token: None } => write!(f, "hello {}", name),
+            ClientMsg::Hello {
+                name,
+                token: Some(token),
+            } => write!(f, "hello {} {}", name, token),
+            ClientMsg::List => f.write_str("list"),
+            ClientMsg::Seek(side) => write!(f, "seek {}", side_name(*side)),
+            ClientMsg::Join(id) => write!(f, "join {}", id),
+            ClientMsg::Watch(id) => write!(f, "watch {}", id),
+            ClientMsg::Leave => f.write_str("leave"),
+            ClientMsg::Say(text) => write!(f, "say {}", text),
+            ClientMsg::Moves => f.write_str("moves"),
+            ClientMsg::Move(row, col) => write!(f, "move {}", square(*row, *col)),
+            ClientMsg::Pass => f.write_str("pass"),
+            ClientMsg::Resign => f.write_str("resign"),
+        }
+    }
+}
+
+impl FromStr for ClientMsg {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let words = rest.split_whitespace().collect::<Vec<_>>();
+        let msg = match (keyword, words.as_slice()) {
+            ("hello", [name]) => ClientMsg::Hello {
+                name: name.to_string(),
+                token: None,
+            },
+            ("hello", [name, token]) => ClientMsg::Hello {
+                name: name.to_string(),
+                token: Some(token.to_string()),
+            },
+            ("list", []) => ClientMsg::List,
+            ("seek", []) => ClientMsg::Seek(Turn::Black),
+            ("seek", [side]) => ClientMsg::Seek(parse_side(side)?),
+            ("join", [id]) => ClientMsg::Join(parse_id(id)?),
+            ("watch", [id]) => ClientMsg::Watch(parse_id(id)?),
+            ("leave", []) => ClientMsg::Leave,
+            ("say", _) if !rest.trim().is_empty() => ClientMsg::Say(rest.trim().to_string()),
+            ("moves", []) => ClientMsg::Moves,
+            ("move", [sq]) => {
+                let (row, col) = parse_square(sq)?;
+                ClientMsg::Move(row, col)
+            }
+            ("pass", []) => ClientMsg::Pass,
+            ("resign", []) => ClientMsg::Resign,
+            _ => return Err(format!("unknown message `{}`", line)),
+        };
+        Ok(msg)
+    }
+}
+
+impl fmt::Display for ServerMsg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerMsg::Welcome { name, token } => write!(f, "welcome {} {}", name, token),
+            ServerMsg::Open { id, name, side } => {
+                write!(f, "open {} {} {}", id, name, side_name(*side))
+            }
+            ServerMsg::Playing { id, black, white } => {
+                write!(f, "playing {} {} {}", id, black, white)
+            }
+            ServerMsg::Listed => f.write_str("listed"),
+            ServerMsg::Closed(id) => write!(f, "closed {}", id),
+            ServerMsg::Game { id, side } => match side {
+                Turn::Neither => write!(f, "game {} spectator", id),
+                side => write!(f, "game {} {}", id, side_name(*side)),
+            },
+            ServerMsg::Waiting => f.write_str("waiting"),
+            ServerMsg::Players { black, white } => write!(f, "players {} {}", black, white),
+            ServerMsg::Board(position) => write!(f, "board {}", position),
+            ServerMsg::Played { side, mv } => match mv {
+                Move::Play(row, col) => {
+                    write!(f, "played {} {}", side_name(*side), square(*row, *col))
+                }
+                _ => write!(f, "played {} pass", side_name(*side)),
+            },
+            ServerMsg::Skip(side) => write!(f, "skip {}", side_name(*side)),
+            ServerMsg::Turn(side) => write!(f, "turn {}", side_name(*side)),
+            ServerMsg::Moves(moves) => {
+                f.write_str("moves")?;
+                for &(row, col) in moves {
+                    write!(f, " {}", square(row, col))?;
+                }
+                Ok(())
+            }
+            ServerMsg::Clock { black, white } => write!(f, "clock {} {}", black, white),
+            ServerMsg::Away { side, seconds } => write!(f, "away {} {}", side_name(*side), seconds),
+            ServerMsg::Back(side) => write!(f, "back {}", side_name(*side)),
+            ServerMsg::Over(outcome) => {
+                write!(
+                    f,
+                    "over {} {} {} {}",
+                    outcome.end.name(),
+                    winner_name(outcome.winner),
+                    outcome.black,
+                    outcome.white
+                )
+            }
+            ServerMsg::Chat { name, text } => write!(f, "chat {} {}", name, text),
+            ServerMsg::Error(text) => write!(f, "error {}", text),
+        }
+    }
+}
+
+impl FromStr for ServerMsg {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let words = rest.split_whitespace().collect::<Vec<_>>();
+        let bad = || format!("unknown message `{}`", line);
+        let msg = match (keyword, words.as_slice()) {
+            ("welcome", [name, token]) => ServerMsg::Welcome {
+                name: name.to_string(),
+                token: token.to_string(),
+            },
+            ("open", [id, name, side]) => ServerMsg::Open {
+                id: parse_id(id)?,
+                name: name.to_string(),
+                side: parse_side(side)?,
+            },
+            ("playing", [id, black, white]) => ServerMsg::Playing {
+                id: parse_id(id)?,
+                black: black.to_string(),
+                white: white.to_string(),
+            },
+            ("listed", []) => ServerMsg::Listed,
+            ("closed", [id]) => ServerMsg::Closed(parse_id(id)?),
+            ("game", [id, "spectator"]) => ServerMsg::Game {
+                id: parse_id(id)?,
+                side: Turn::Neither,
+            },
+            ("game", [id, side]) => ServerMsg::Game {
+                id: parse_id(id)?,
+                side: parse_side(side)?,
+            },
+            ("waiting", []) => ServerMsg::Waiting,
+            ("players", [black, white]) => ServerMsg::Players {
+                black: black.to_string(),
+                white: white.to_string(),
+            },
+            ("board", _) if !rest.is_empty() => ServerMsg::Board(rest.to_string()),
+            ("played", [side, "pass"]) => ServerMsg::Played {
+                side: parse_side(side)?,
+                mv: Move::Pass,
+            },
+            ("played", [side, sq]) => {
+                let (row, col) = parse_square(sq)?;
+                ServerMsg::Played {
+                    side: parse_side(side)?,
+                    mv: Move::Play(row, col),
+                }
+            }
+            ("skip", [side]) => ServerMsg::Skip(parse_side(side)?),
+            ("turn", [side]) => ServerMsg::Turn(parse_side(side)?),
+            ("moves", squares) => ServerMsg::Moves(
+                squares
+                    .iter()
+                    .map(|sq| parse_square(sq))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("clock", [black, white]) => ServerMsg::Clock {
+                black: black.parse().map_err(|_| bad())?,
+                white: white.parse().map_err(|_| bad())?,
+            },
+            ("away", [side, seconds]) => ServerMsg::Away {
+                side: parse_side(side)?,
+                seconds: seconds.parse().map_err(|_| bad())?,
+            },
+            ("back", [side]) => ServerMsg::Back(parse_side(side)?),
+            ("over", [end, winner, black, white]) => ServerMsg::Over(Outcome {
+                end: End::parse(end).ok_or_else(bad)?,
+                winner: parse_winner(winner)?,
+                black: black.parse().map_err(|_| bad())?,
+                white: white.parse().map_err(|_| bad())?,
+            }),
+            ("chat", [name, ..]) => ServerMsg::Chat {
+                name: name.to_string(),
+                text: rest[name.len()..].trim().to_string(),
+            },
+            ("error", _) => ServerMsg::Error(rest.to_string()),
+            _ => return Err(bad()),
+        };
+        Ok(msg)
+    }
+}
+
+impl End {
+    pub fn name(self) -> &'static str {
+        match self {
+            End::Finished => "finished",
+            End::Resigned => "resigned",
+            End::Forfeit => "forfeit",
+            End::Time => "time",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<End> {
+        let ends = [End::Finished, End::Resigned, End::Forfeit, End::Time];
+        ends.iter().copied().find(|end| end.name() == name)
+    }
+}
+
+pub fn side_name(side: Turn) -> &'static str {
+    match side {
+        Turn::White => "white",
+        _ => "black",
+    }
+}
+
+pub fn parse_side(side: &str) -> Result<Turn, String> {
+    match side {
+        "black" => Ok(Turn::Black),
+        "white" => Ok(Turn::White),
+        _ => Err(format!("`{}` is not `black` or `white`", side)),
+    }
+}
+
+// A side, or `draw` for `Turn::Neither`.
+pub fn winner_name(winner: Turn) -> &'static str {
+    match winner {
+        Turn::Neither => "draw",
+        side => side_name(side),
+    }
+}
+
+pub fn parse_winner(winner: &str) -> Result<Turn, String> {
+    match winner {
+        "draw" => Ok(Turn::Neither),
+        side => parse_side(side),
+    }
+}
+
+pub fn parse_id(id: &str) -> Result<u32, String> {
+    id.parse()
+        .map_err(|_| format!("`{}` is not a game number", id))
+}
+
+pub fn square(row: usize, col: usize) -> String {
+    Notation::Standard.format(row, col)
+}
+
+// A single square, on any board size. Whether it is on this game's
+// board is for the game to say.
+pub fn parse_square(text: &str) -> Result<(usize, usize), String> {
+    match Notation::Standard.parse(text, MAX_SIZE).as_deref() {
+        Some(&[square]) => Ok(square),
+        _ => Err(format!("`{}` is not a square", text)),
+    }
+}
+
+// Names go on the wire as one word.
+pub fn clean_name(name: &str) -> String {
+    let words = name.split_whitespace().collect::<Vec<_>>();
+    match words.join("_") {
+        name if name.is_empty() => "anonymous".to_string(),
+        name => name,
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::board::Variant;
+use crate::config::{self, Config};
+use crate::engine::{self, Engine, Transpositions};
+use crate::events::{self, Move};
+use crate::profiles::{self, Change};
+use crate::setup::{self, Holes, Setup};
+use crate::theme;
+
+// Positions near the end of a game where only one move wins, to find that
+// move. They come from a bundled set, or are found by playing games out
+// and keeping the positions the solver finds a single winning move in.
+//
+// Everyone who tries them gets a puzzle rating and a streak of puzzles
+// solved in a row, kept in `puzzles.json` next to the config file.
+
+// The positions that come with the game, on the usual 8x8 board.
+const BUNDLED: &str = include_str!("puzzles.txt");
+// The fewest and most empty squares a new puzzle can have.
+pub const MIN_EMPTIES: usize = 4;
+pub const MAX_EMPTIES: usize = 14;
+// How good the play is that leads to new puzzles, and how often a move
+// is picked at random instead, so they don't all look alike.
+const PLAY_LEVEL: u8 = 1;
+const RANDOM_MOVES: f64 = 0.3;
+// How many games `generate` plays out before giving up.
+const MAX_GAMES: usize = 500;
+// How a puzzle's rating follows from its empties and moves to pick from.
+const BASE_RATING: f64 = 1000.0;
+const PER_EMPTY: f64 = 40.0;
+const PER_MOVE: f64 = 25.0;
+// The most one puzzle can move a rating.
+const K: f64 = 32.0;
+const TABLE_SLOTS: usize = 1 << 18;
+
+// A position with a single winning move, worked out by the solver.
+pub struct Puzzle {
+    pub setup: Setup,
+    pub answer: (usize, usize),
+    // Every legal move with its exact final margin, best first.
+    pub scores: Vec<((usize, usize), i32)>,
+    pub rating: f64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Solvers {
+    pub players: BTreeMap<String, Solver>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Solver {
+    pub rating: f64,
+    pub solved: u32,
+    pub tried: u32,
+    pub streak: u32,
+    pub best_streak: u32,
+}
+
+enum Answer {
+    Play((usize, usize)),
+    GiveUp,
+    Quit,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self {
+            rating: profiles::START_RATING,
+            solved: 0,
+            tried: 0,
+            streak: 0,
+            best_streak: 0,
+        }
+    }
+}
+
+impl Puzzle {
+    // Solves `setup`, and makes a puzzle of it if exactly one move wins.
+    // A position with only one legal move is no puzzle.
+    pub fn new(setup: Setup, table: &Arc<Transpositions>) -> Option<Puzzle> {
+        let empties = engine::empties(&setup.board);
+        if empties > MAX_EMPTIES {
+            return None;
+        }
+        let mut engine = Engine::with_table(None, table.clone());
+        let scores = engine.analyze(&setup.board, setup.turn, empties as u32);
+        let answer = match scores[..] {
+            [(answer, best), (_, second), ..] if best > 0 && second <= 0 => answer,
+            _ => return None,
+        };
+        let rating =
+            BASE_RATING + PER_EMPTY * empties as f64 + PER_MOVE * (scores.len() - 1) as f64;
+        Some(Puzzle {
+            setup,
+            answer,
+            scores,
+            rating,
+        })
+    }
+
+    // What the right move wins by.
+    pub fn margin(&self) -> i32 {
+        self.scores[0].1
+    }
+
+    fn score(&self, square: (usize, usize)) -> Option<i32> {
+        self.scores
+            .iter()
+            .find(|&&(s, _)| s == square)
+            .map(|&(_, score)| score)
+    }
+}
+
+impl Solvers {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        profiles::read_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        profiles::write_json(path, self)
+    }
+
+    pub fn rating(&self, name: &str) -> f64 {
+        self.players
+            .get(name)
+            .map_or(profiles::START_RATING, |s| s.rating)
+    }
+
+    // Counts `name`'s try at `puzzle`, and moves their rating.
+    pub fn record(&mut self, name: &str, puzzle: &Puzzle, solved: bool) -> Change {
+        let solver = self.players.entry(name.to_string()).or_default();
+        let score = if solved { 1.0 } else { 0.0 };
+        let delta = K * (score - profiles::expected(solver.rating, puzzle.rating));
+        solver.rating += delta;
+        solver.tried += 1;
+        if solved {
+            solver.solved += 1;
+            solver.streak += 1;
+            solver.best_streak = solver.best_streak.max(solver.streak);
+        } else {
+            solver.streak = 0;
+        }
+        Change {
+            rating: solver.rating,
+            delta,
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("puzzles.json"))
+}
+
+// The bundled positions, one per line. They are checked as they come up.
+pub fn bundled() -> Vec<Setup> {
+    BUNDLED
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Setup::read(line).expect("bundled puzzles are valid positions"))
+        .collect()
+}
+
+// Whether the bundled puzzles are any use with these rules.
+fn bundled_fits(config: &Config) -> bool {
+    config.size == 8 && config.variant == Variant::Othello && matches!(config.holes, Holes::None)
+}
+
+// Plays games out from `start` until one reaches a position with
+// `empties` empty squares and a single winning move, or `MAX_GAMES` have
+// gone by without one.
+pub fn generate(
+    start: &Setup,
+    empties: usize,
+    rng: &mut impl Rng,
+    table: &Arc<Transpositions>,
+) -> Result<Puzzle, String> {
+    for _ in 0..MAX_GAMES {
+        let (mut board, mut turn) = (start.board.clone(), start.turn);
+        while engine::empties(&board) > empties {
+            let legal = Move::legal_moves(&board, turn);
+            let square = if rng.gen_bool(RANDOM_MOVES) {
+                legal.choose(rng).copied()
+            } else {
+                Engine::new(None).choose(&board, turn, PLAY_LEVEL)
+            };
+            let (row, col) = match square {
+                Some(square) => square,
+                None => break,
+            };
+            board = Move::after(&board, turn, row, col).expect("legal move");
+            if Move::has_moves(&board, turn.opponent()) {
+                turn = turn.opponent();
+            } else if !Move::has_moves(&board, turn) {
+                break;
+            }
+        }
+        if engine::empties(&board) != empties {
+            continue;
+        }
+        if let Some(puzzle) = Puzzle::new(Setup { board, turn }, table) {
+            return Ok(puzzle);
+        }
+    }
+    Err(format!(
+        "no puzzle with {} empties turned up in {} games",
+        empties, MAX_GAMES
+    ))
+}
+
+// `othello puzzle`: serves puzzles to `player` until they quit, from the
+// bundled set and then new ones, or only new ones if `fresh`.
+pub fn run(player: &str, fresh: bool, empties: usize) -> Result<(), String> {
+    let start_empties = engine::empties(&setup::start().board);
+    if empties >= start_empties {
+        return Err(format!(
+            "this start has only {} empty squares, so puzzles need fewer than that",
+            start_empties
+        ));
+    }
+    let path = path().ok_or("no home directory to keep puzzle ratings in")?;
+    let mut solvers = Solvers::load(&path)?;
+    let config = config::current();
+    let theme = theme::current();
+    let mut rng = rand::thread_rng();
+    let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+    let mut bundled = if fresh || !bundled_fits(config) {
+        Vec::new()
+    } else {
+        bundled()
+    };
+    bundled.shuffle(&mut rng);
+    let start_rating = solvers.rating(player);
+    let (mut tried, mut solved) = (0, 0);
+
+    loop {
+        let puzzle = match bundled.pop() {
+            Some(setup) => match Puzzle::new(setup, &table) {
+                Some(puzzle) => puzzle,
+                None => continue,
+            },
+            None => {
+                println!("Looking for a puzzle...");
+                generate(setup::start(), empties, &mut rng, &table)?
+            }
+        };
+        let answer = match ask(&puzzle, player, &solvers)? {
+            Answer::Quit => break,
+            Answer::Play(square) => Some(square),
+            Answer::GiveUp => None,
+        };
+
+        let notation = config.notation;
+        let right = notation.format(puzzle.answer.0, puzzle.answer.1);
+        let correct = answer == Some(puzzle.answer);
+        let verdict = match answer {
+            _ if correct => format!("Correct: {} wins by {}.", right, puzzle.margin()),
+            Some(square) => {
+                let score = puzzle.score(square).expect("a legal move");
+                let result = match score {
+                    0 => "only draws".to_string(),
+                    _ => format!("loses by {}", -score),
+                };
+                let played = notation.format(square.0, square.1);
+                format!(
+                    "{} {}, but {} wins by {}.",
+                    played,
+                    result,
+                    right,
+                    puzzle.margin()
+                )
+            }
+            None => format!("{} wins by {}.", right, puzzle.margin()),
+        };
+        let style = if correct { &theme.result } else { &theme.error };
+        println!("{}", style.paint(&verdict));
+
+        let change = solvers.record(player, &puzzle, correct);
+        solvers.save(&path)?;
+        tried += 1;
+        solved += correct as u32;
+        let solver = &solvers.players[player];
+        println!(
+            "Puzzle rating {}. Streak {}, best {}.",
+            change, solver.streak, solver.best_streak
+        );
+
+        println!("Press Enter for the next puzzle, or enter `q` to quit.");
+        match events::read_line(None).map_err(|e| e.to_string())? {
+            Some(line) if line.trim() == "q" => break,
+            _ => {}
+        }
+    }
+
+    if tried > 0 {
+        let rating = solvers.rating(player);
+        println!(
+            "Solved {} of {}. {}'s puzzle rating: {:.0} ({:+.0}).",
+            solved,
+            tried,
+            player,
+            rating,
+            rating - start_rating
+        );
+    }
+    Ok(())
+}
+
+// Shows `puzzle` until `player` picks a legal move, gives up or quits.
+fn ask(puzzle: &Puzzle, player: &str, solvers: &Solvers) -> Result<Answer, String> {
+    let config = config::current();
+    let setup = &puzzle.setup;
+    let solver = solvers.players.get(player);
+    let mut notice: Option<String> = None;
+    loop {
+        setup.board.draw(setup.turn, None, None);
+        println!("{}", setup);
+        println!(
+            "{} to move and win. Only one move does.",
+            setup::side(setup.turn)
+        );
+        println!(
+            "Puzzle rated {:.0}. {} is rated {:.0}, on a streak of {}.",
+            puzzle.rating,
+            player,
+            solvers.rating(player),
+            solver.map_or(0, |s| s.streak)
+        );
+        if let Some(notice) = notice.take() {
+            println!("{}", theme::current().error.paint(&notice));
+        }
+        println!("Enter your move, `s` to see the answer or `q` to quit.");
+
+        let line = match events::read_line(None).map_err(|e| e.to_string())? {
+            Some(line) => line,
+            None => return Ok(Answer::Quit),
+        };
+        let line = line.trim();
+        match line {
+            "q" => return Ok(Answer::Quit),
+            "s" => return Ok(Answer::GiveUp),
+            _ => match config.notation.parse(line, setup.board.size).as_deref() {
+                Some(&[square]) if puzzle.score(square).is_some() => {
+                    return Ok(Answer::Play(square))
+                }
+                Some(&[_]) => notice = Some(format!("{} is not a legal move.", line)),
+                _ => notice = Some(format!("`{}` is not a square.", line)),
+            },
+        }
+    }
+}
+
+// `othello puzzle --scores`: everyone's puzzle ratings, best first.
+pub fn scores() -> Result<(), String> {
+    let path = path().ok_or("no home directory to keep puzzle ratings in")?;
+    let solvers = Solvers::load(&path)?;
+    if solvers.players.is_empty() {
+        println!("No puzzles tried yet.");
+        return Ok(());
+    }
+    let mut players: Vec<_> = solvers.players.iter().collect();
+    players.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating).then(a.0.cmp(b.0)));
+    let width = players
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "  {:>3}  {:<width$}  {:>6}  {:>6}  {:>5}  {:>6}  {:>4}",
+        "#",
+        "Name",
+        "Rating",
+        "Solved",
+        "Tried",
+        "Streak",
+        "Best",
+        width = width
+    );
+    for (rank, (name, solver)) in players.iter().enumerate() {
+        println!(
+            "  {:>3}  {:<width$}  {:>6.0}  {:>6}  {:>5}  {:>6}  {:>4}",
+            rank + 1,
+            name,
+            solver.rating,
+            solver.solved,
+            solver.tried,
+            solver.streak,
+            solver.best_streak,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_puzzles_have_one_winning_move() {
+        let mut rng = rand::thread_rng();
+        let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+        for _ in 0..3 {
+            let puzzle = generate(&Setup::standard(8), MIN_EMPTIES + 2, &mut rng, &table).unwrap();
+            assert_eq!(engine::empties(&puzzle.setup.board), MIN_EMPTIES + 2);
+            let winning: Vec<_> = puzzle.scores.iter().filter(|&&(_, s)| s > 0).collect();
+            assert_eq!(winning.len(), 1);
+            assert!(winning[0].0 == puzzle.answer);
+        }
+    }
+
+    #[test]
+    fn gives_up_on_puzzles_it_cannot_find() {
+        let mut rng = rand::thread_rng();
+        let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+        // Games on a 4x4 board start with only 12 empties.
+        let err = generate(&Setup::standard(4), 13, &mut rng, &table).err();
+        assert!(err.unwrap().contains("no puzzle with 13 empties"));
+    }
+
+    #[test]
+    fn keeps_ratings_and_streaks() {
+        let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+        let puzzle = Puzzle::new(bundled().remove(0), &table).unwrap();
+        let mut solvers = Solvers::default();
+        let up = solvers.record("alice", &puzzle, true);
+        solvers.record("alice", &puzzle, true);
+        assert!(up.delta > 0.0);
+        assert_eq!(solvers.players["alice"].streak, 2);
+        let down = solvers.record("alice", &puzzle, false);
+        assert!(down.delta < 0.0);
+        let alice = &solvers.players["alice"];
+        assert_eq!((alice.solved, alice.tried), (2, 3));
+        assert_eq!((alice.streak, alice.best_streak), (0, 2));
+        assert_eq!(solvers.rating("bob"), profiles::START_RATING);
+    }
+
+    #[test]
+    #[ignore] // Slow in debug builds; run with `cargo test --release -- --ignored`.
+    fn every_bundled_puzzle_has_one_winning_move() {
+        let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+        for setup in bundled() {
+            let text = setup.format();
+            assert!(Puzzle::new(setup, &table).is_some(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+
+use crate::board::Board;
+use crate::config;
+use crate::engine::{self, Engine, Transpositions};
+use crate::events::{self, Move, Turn};
+use crate::setup::{self, Setup};
+use crate::theme;
+use crate::transcript::{self, Position};
+
+// Post-game analysis: every move of a game held up against what the
+// engine would have played, and how much it gave away.
+//
+// Scores are in discs, as the engine counts them: exact near the end of
+// the game, and an estimate before that.
+
+// The AI level whose search checks the moves, unless asked otherwise.
+pub const DEFAULT_LEVEL: u8 = 6;
+// The most a move can fall short of the best one, in discs, and still count
+// as good, an inaccuracy or a mistake. Anything worse is a blunder.
+const GOOD: i32 = 2;
+const INACCURACY: i32 = 4;
+const MISTAKE: i32 = 8;
+// How quickly accuracy falls away as a move loses discs: a move that
+// gives away this many scores about 37%.
+const ACCURACY_SCALE: f64 = 16.0;
+// Rows of the evaluation graph either side of the axis, and how wide it
+// gets before neighbouring plies share a column.
+const GRAPH_HEIGHT: usize = 5;
+const GRAPH_WIDTH: usize = 64;
+// The smallest lead the graph reaches the top for, so that an even game
+// stays flat.
+const GRAPH_SCALE: i32 = 10;
+// How big a transposition table the analysis shares between positions.
+const TABLE_SLOTS: usize = 1 << 18;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Class {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+// One move of the game, as the engine saw it.
+pub struct Reviewed {
+    pub turn: Turn,
+    pub played: Move,
+    // What the engine would have played instead.
+    pub best: Move,
+    // How many discs short of `best` the move fell.
+    pub loss: i32,
+    pub class: Class,
+}
+
+pub struct Review {
+    pub moves: Vec<Reviewed>,
+    // How every position stood, from Black's side, the start first.
+    pub evals: Vec<i32>,
+}
+
+impl Class {
+    fn of(loss: i32) -> Class {
+        match loss {
+            i32::MIN..=0 => Class::Best,
+            1..=GOOD => Class::Good,
+            _ if loss <= INACCURACY => Class::Inaccuracy,
+            _ if loss <= MISTAKE => Class::Mistake,
+            _ => Class::Blunder,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Class::Best => "best",
+            Class::Good => "good",
+            Class::Inaccuracy => "inaccuracy",
+            Class::Mistake => "mistake",
+            Class::Blunder => "blunder",
+        }
+    }
+}
+
+// Checks every move of a game from `start` with an AI of `level`'s search.
+pub fn review(start: &Setup, moves: &[Move], level: u8) -> Result<Review, String> {
+    let positions = transcript::replay_from(start, moves)?;
+    let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+    let mut reviewed = Vec::new();
+    let mut evals = Vec::new();
+    for (i, position) in positions.iter().enumerate() {
+        let next = positions.get(i + 1);
+        if position.is_over() || next.is_none() {
+            evals.push(for_black(
+                standing(&position.board, position.turn, level, &table),
+                position.turn,
+            ));
+            continue;
+        }
+        let played = next
+            .and_then(|next| next.played)
+            .expect("a move to the next position");
+        let mut engine = Engine::with_table(None, table.clone());
+        let depth = depth(&position.board, level);
+        let scored = engine.analyze(&position.board, position.turn, depth);
+        let (best, best_score) = scored
+            .first()
+            .map(|&((row, col), score)| (Move::Play(row, col), score))
+            .expect("a legal move, or the turn would have been skipped");
+        let score = match played {
+            Move::Play(row, col) => scored
+                .iter()
+                .find(|&&(square, _)| square == (row, col))
+                .map(|&(_, score)| score)
+                .expect("a legal move"),
+            _ => pass_score(&position.board, position.turn, depth, &table),
+        };
+        let loss = (best_score - score).max(0);
+        evals.push(for_black(best_score, position.turn));
+        reviewed.push(Reviewed {
+            turn: position.turn,
+            played,
+            best,
+            loss,
+            class: Class::of(loss),
+        });
+    }
+    Ok(Review {
+        moves: reviewed,
+        evals,
+    })
+}
+
+// How deep to look from `board`: to the end once that's close enough.
+fn depth(board: &Board, level: u8) -> u32 {
+    let (depth, solve) = Engine::depths(level);
+    let empties = engine::empties(board);
+    if empties <= solve {
+        empties as u32
+    } else {
+        depth
+    }
+}
+
+// What passing is worth: whatever the other side makes of the extra move.
+fn pass_score(board: &Board, turn: Turn, depth: u32, table: &Arc<Transpositions>) -> i32 {
+    let mut engine = Engine::with_table(None, table.clone());
+    let replies = engine.analyze(board, turn.opponent(), depth.max(2) - 1);
+    -replies.first().map_or(0, |&(_, score)| score)
+}
+
+// How a position stands for `turn`, where the game may already be over.
+fn standing(board: &Board, turn: Turn, level: u8, table: &Arc<Transpositions>) -> i32 {
+    let mut engine = Engine::with_table(None, table.clone());
+    let (depth, solve) = Engine::depths(level);
+    let over = !Move::has_moves(board, turn) && !Move::has_moves(board, turn.opponent());
+    if over || engine::empties(board) <= solve {
+        return engine.solve(board, turn).1;
+    }
+    engine
+        .analyze(board, turn, depth)
+        .first()
+        .map_or(0, |&(_, score)| score)
+}
+
+// How `position` stands, from Black's side, to the search of an AI of
+// `level`.
+pub fn evaluate(position: &Position, level: u8) -> i32 {
+    let table = Arc::new(Transpositions::new(TABLE_SLOTS));
+    for_black(
+        standing(&position.board, position.turn, level, &table),
+        position.turn,
+    )
+}
+
+fn for_black(score: i32, turn: Turn) -> i32 {
+    if turn == Turn::White {
+        -score
+    } else {
+        score
+    }
+}
+
+impl Review {
+    // The average of how close each of `turn`'s moves came to the best,
+    // out of 100.
+    pub fn accuracy(&self, turn: Turn) -> Option<f64> {
+        let scores: Vec<f64> = self
+            .moves
+            .iter()
+            .filter(|mv| mv.turn == turn)
+            .map(|mv| 100.0 * (-f64::from(mv.loss) / ACCURACY_SCALE).exp())
+            .collect();
+        if scores.is_empty() {
+            return None;
+        }
+        Some(scores.iter().sum::<f64>() / scores.len() as f64)
+    }
+
+    fn count(&self, turn: Turn, class: Class) -> usize {
+        self.moves
+            .iter()
+            .filter(|mv| mv.turn == turn && mv.class == class)
+            .count()
+    }
+
+    pub fn print(&self) {
+        let glyphs = theme::glyphs();
+        let theme = theme::current();
+        for (i, mv) in self.moves.iter().enumerate() {
+            // Padded before painting, which would throw the width off.
+            let class = format!("{:<10}", mv.class.name());
+            let class = match mv.class {
+                Class::Mistake | Class::Blunder => theme.error.paint(&class).to_string(),
+                _ => class,
+            };
+            let instead = match mv.class {
+                Class::Best => String::new(),
+                _ => format!("  {} was {} better", mv.best, mv.loss),
+            };
+            let line = format!(
+                "{:>4}. {} {:<5}{:>+4}  {}{}",
+                i + 1,
+                glyphs.mark(mv.turn),
+                mv.played.to_string(),
+                self.evals[i + 1],
+                class,
+                instead
+            );
+            println!("{}", line.trim_end());
+        }
+
+        println!();
+        for line in self.graph() {
+            println!("{}", line);
+        }
+
+        println!();
+        let names = [Turn::Black, Turn::White].map(|turn| config::current().name(turn));
+        let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+        for (turn, name) in [Turn::Black, Turn::White].iter().zip(names.iter()) {
+            let accuracy = match self.accuracy(*turn) {
+                Some(accuracy) => accuracy,
+                None => continue,
+            };
+            println!(
+                "{} {:<width$}  accuracy {:>3.0}%  best {}, good {}, inaccuracies {}, mistakes {}, blunders {}",
+                glyphs.mark(*turn),
+                name,
+                accuracy,
+                self.count(*turn, Class::Best),
+                self.count(*turn, Class::Good),
+                self.count(*turn, Class::Inaccuracy),
+                self.count(*turn, Class::Mistake),
+                self.count(*turn, Class::Blunder),
+                width = width
+            );
+        }
+    }
+
+    // The evaluation after every ply as bars, Black's discs above the axis
+    // when Black is ahead and White's below when White is.
+    pub fn graph(&self) -> Vec<String> {
+        let glyphs = theme::glyphs();
+        let step = self.evals.len().div_ceil(GRAPH_WIDTH).max(1);
+        // The last of each run of plies sharing a column.
+        let columns: Vec<i32> = self
+            .evals
+            .chunks(step)
+            .map(|chunk| *chunk.last().expect("non-empty chunk"))
+            .collect();
+        let scale = columns
+            .iter()
+            .map(|eval| eval.abs())
+            .max()
+            .unwrap_or(0)
+            .max(GRAPH_SCALE);
+        let height = |eval: i32| -> usize {
+            let rows = (eval.abs() as f64 / scale as f64 * GRAPH_HEIGHT as f64).round() as usize;
+            if eval != 0 {
+                rows.max(1)
+            } else {
+                0
+            }
+        };
+
+        let mut lines = Vec::new();
+        for row in (1..=GRAPH_HEIGHT).rev() {
+            let label = if row == GRAPH_HEIGHT {
+                format!("{:+}", scale)
+            } else {
+                String::new()
+            };
+            let bars: String = columns
+                .iter()
+                .map(|&eval| {
+                    if eval > 0 && height(eval) >= row {
+                        glyphs.black_mark
+                    } else {
+                        " "
+                    }
+                })
+                .collect();
+            lines.push(format!("{:>5} |{}", label, bars.trim_end()));
+        }
+        lines.push(format!("{:>5} +{}", 0, "-".repeat(columns.len())));
+        for row in 1..=GRAPH_HEIGHT {
+            let label = if row == GRAPH_HEIGHT {
+                format!("{:+}", -scale)
+            } else {
+                String::new()
+            };
+            let bars: String = columns
+                .iter()
+                .map(|&eval| {
+                    if eval < 0 && height(eval) >= row {
+                        glyphs.white_mark
+                    } else {
+                        " "
+                    }
+                })
+                .collect();
+            lines.push(format!("{:>5} |{}", label, bars.trim_end()));
+        }
+        lines
+    }
+}
+
+// `othello review`: analyzes a whole game from its transcript.
+pub fn run(text: &str, level: u8) -> Result<(), String> {
+    let game = transcript::read_game(text, config::current().notation)?;
+    let start = game.start.unwrap_or_else(|| setup::start().clone());
+    start.fits(config::current().size)?;
+    let moves = game.moves;
+    if moves.is_empty() {
+        return Err("no moves to analyze".to_string());
+    }
+    // Checked before the wait, rather than after it.
+    transcript::replay_from(&start, &moves)?;
+    println!("Analyzing {} moves at level {}...\n", moves.len(), level);
+    review(&start, &moves, level)?.print();
+    Ok(())
+}
+
+// Asks whether to analyze the game that just ended, when there is
+// someone at a terminal to answer.
+pub fn offer(moves: &[Move]) {
+    let moves: Vec<Move> = moves
+        .iter()
+        .copied()
+        .filter(|mv| matches!(mv, Move::Play(_, _) | Move::Pass))
+        .collect();
+    if moves.is_empty() || !io::stdin().is_terminal() {
+        return;
+    }
+    print!("Analyze the game? [y/N] ");
+    let _ = io::stdout().flush();
+    match events::read_line(None) {
+        Ok(Some(answer)) if answer.trim().eq_ignore_ascii_case("y") => {}
+        _ => return,
+    }
+    println!();
+    match review(setup::start(), &moves, DEFAULT_LEVEL) {
+        Ok(review) => review.print(),
+        Err(e) => eprintln!("{}", theme::current().error.paint(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_moves_by_what_they_give_away() {
+        assert_eq!(Class::of(0), Class::Best);
+        assert_eq!(Class::of(2), Class::Good);
+        assert_eq!(Class::of(3), Class::Inaccuracy);
+        assert_eq!(Class::of(8), Class::Mistake);
+        assert_eq!(Class::of(9), Class::Blunder);
+    }
+
+    #[test]
+    fn reviews_a_game_against_the_engine() {
+        // Whatever comes first on the board, which is rarely the best.
+        let mut position = Setup::standard(8);
+        let mut moves = Vec::new();
+        for _ in 0..12 {
+            let (row, col) = Move::legal_moves(&position.board, position.turn)[0];
+            moves.push(Move::Play(row, col));
+            let last = transcript::replay(&moves).unwrap().pop().unwrap();
+            position = Setup {
+                board: last.board,
+                turn: last.turn,
+            };
+        }
+        let review = review(&Setup::standard(8), &moves, 3).unwrap();
+        assert_eq!(review.moves.len(), moves.len());
+        assert_eq!(review.evals.len(), moves.len() + 1);
+        // The best move loses nothing, and nothing gains on the best.
+        for mv in &review.moves {
+            assert!(mv.loss >= 0);
+            if mv.class == Class::Best {
+                assert_eq!(mv.loss, 0);
+            }
+        }
+        let accuracy = review.accuracy(Turn::Black).unwrap();
+        assert!((0.0..=100.0).contains(&accuracy));
+        assert_eq!(review.graph().len(), 2 * GRAPH_HEIGHT + 1);
+    }
+}
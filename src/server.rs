@@ -0,0 +1,1077 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::archive::{self, Entry};
+use crate::board::Board;
+use crate::clock::{Clock, TimeControl};
+use crate::config;
+use crate::events::{InputHandler, Move, PlayabilityChecker, Turn};
+use crate::profiles::{self, Profiles};
+use crate::protocol::{self, ClientMsg, End, Outcome, ServerMsg};
+use crate::setup::{self, Setup};
+use crate::websocket;
+
+// One game on the server, which alone decides what is legal.
+pub struct Table {
+    pub board: Board,
+    // Whoever is to move, until the game is over.
+    pub turn: Turn,
+    pub history: Vec<Move>,
+    pub outcome: Option<Outcome>,
+    pub clock: Option<Clock>,
+    // When the flag of the side to move falls, once the clock is running.
+    pub flag: Option<Instant>,
+}
+
+impl Table {
+    // A game from this run's starting position.
+    pub fn new(control: Option<TimeControl>) -> Self {
+        let start = setup::start();
+        Self {
+            board: start.board.clone(),
+            turn: start.turn,
+            history: Vec::new(),
+            outcome: None,
+            clock: control.map(Clock::new),
+            flag: None,
+        }
+    }
+
+    // Starts the clock of the side to move, once both players are in.
+    pub fn start(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            self.flag = Some(clock.start(self.turn));
+        }
+    }
+
+    // The time left on both clocks, if the game has them.
+    pub fn times(&self) -> Option<ServerMsg> {
+        let clock = self.clock.as_ref()?;
+        let left = |side| clock.remaining(side).as_millis() as u64;
+        Some(ServerMsg::Clock {
+            black: left(Turn::Black),
+            white: left(Turn::White),
+        })
+    }
+
+    // The legal moves of the side to move.
+    pub fn moves(&self) -> Vec<(usize, usize)> {
+        match self.outcome {
+            Some(_) => Vec::new(),
+            None => Move::legal_moves(&self.board, self.turn),
+        }
+    }
+
+    // Ends the game if the side to move has run out of time.
+    pub fn check_flag(&mut self, now: Instant) -> Option<ServerMsg> {
+        match self.flag {
+            Some(flag) if now >= flag && self.outcome.is_none() => {
+                if let Some(clock) = self.clock.as_mut() {
+                    clock.stop(self.turn, false);
+                }
+                self.flag = None;
+                Some(self.finish(End::Time, self.turn.opponent()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn position(&self) -> String {
+        let setup = Setup {
+            board: self.board.clone(),
+            turn: self.turn,
+        };
+        setup.format()
+    }
+
+    // Plays `mv` for `side`, or says why it can't be played. Returns what
+    // the players need to hear about it.
+    pub fn play(&mut self, side: Turn, mv: Move) -> Result<Vec<ServerMsg>, String> {
+        if self.outcome.is_some() {
+            return Err("the game is over".to_string());
+        }
+        if side != self.turn {
+            return Err("it is not your turn".to_string());
+        }
+        let before = self.board.clone();
+        match mv {
+            Move::Play(row, col) => {
+                if row > self.board.size || col > self.board.size {
+                    return Err(format!("{} is off the board", protocol::square(row, col)));
+                }
+                Move::check_move(&mut self.board, side, row, col).map_err(|e| e.to_string())?;
+            }
+            Move::Pass if !Move::check_playablity(&self.board, side) => {
+                return Err("cannot pass, the opponent has no move".to_string());
+            }
+            Move::Pass => {}
+            Move::Resign => {
+                if let Some(clock) = self.clock.as_mut() {
+                    clock.stop(side, false);
+                }
+                return Ok(vec![self.finish(End::Resigned, side.opponent())]);
+            }
+            _ => return Err(format!("`{}` is not a move", mv)),
+        }
+        if let Some(clock) = self.clock.as_mut() {
+            if !clock.stop(side, true) {
+                return Ok(vec![self.finish(End::Time, side.opponent())]);
+            }
+        }
+
+        let (turn, result) = Move::apply_move(&mut self.board, side, mv);
+        self.board.record_changes(&before);
+        self.history.push(mv);
+        // Once the game is over, `turn` is the winner instead.
+        let over = matches!(result, Some(Move::Win(_, _)));
+        if !over {
+            self.turn = turn;
+        }
+        let mut msgs = vec![
+            ServerMsg::Played { side, mv },
+            ServerMsg::Board(self.position()),
+        ];
+        if over {
+            msgs.push(self.finish(End::Finished, turn));
+        } else {
+            if let Some(Move::Skip) = result {
+                msgs.push(ServerMsg::Skip(side.opponent()));
+            }
+            msgs.push(ServerMsg::Turn(turn));
+            self.start();
+            msgs.extend(self.times());
+        }
+        Ok(msgs)
+    }
+
+    // Ends the game in `winner`'s favor, or a draw for `Turn::Neither`.
+    pub fn finish(&mut self, end: End, winner: Turn) -> ServerMsg {
+        let (black, white) = self.board.count_discs();
+        let outcome = Outcome {
+            end,
+            winner,
+            black,
+            white,
+        };
+        self.outcome = Some(outcome);
+        self.flag = None;
+        ServerMsg::Over(outcome)
+    }
+}
+
+// What the connections have to tell the server, all over one channel.
+pub enum Event {
+    Connected(usize, Conn),
+    Line(usize, String),
+    Msg(usize, ClientMsg),
+    Closed(usize),
+}
+
+// How to reach a connection: messages for the thread that writes to it,
+// as lines for `connect` or as JSON for WebSocket clients. The server
+// never writes to a socket itself, so a client that stops reading can't
+// hold up anyone else.
+pub type Conn = Sender<ServerMsg>;
+
+// How long a write to a line connection can take before the client is
+// taken to have stopped reading, and is let go.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connections are numbered across all listeners.
+static NEXT_CONN: AtomicUsize = AtomicUsize::new(0);
+
+pub fn next_conn() -> usize {
+    NEXT_CONN.fetch_add(1, Ordering::Relaxed)
+}
+
+// Where a logged-in user is.
+#[derive(Clone, Copy, PartialEq)]
+enum Place {
+    Lobby,
+    Playing(u32, Turn),
+    Watching(u32),
+}
+
+struct User {
+    name: String,
+    conn: Option<usize>,
+    place: Place,
+    // When a player in a game lost the connection.
+    left: Option<Instant>,
+}
+
+// A game and who is at it. Seats hold the players' tokens.
+struct Room {
+    table: Table,
+    // Black, then white.
+    seats: [Option<String>; 2],
+    watchers: Vec<String>,
+}
+
+struct Server {
+    conns: HashMap<usize, Conn>,
+    // Logged-in users by token, and the token each connection logged in with.
+    users: HashMap<String, User>,
+    logins: HashMap<usize, String>,
+    games: BTreeMap<u32, Room>,
+    next_game: u32,
+    reconnect: Duration,
+    clock: Option<TimeControl>,
+    // Where finished games are rated and archived, if anywhere.
+    records: Option<PathBuf>,
+}
+
+// `othello serve`: hosts games for any number of `othello connect`s, and
+// for browsers on `websocket` if given.
+pub fn serve(
+    addr: &str,
+    websocket: Option<&str>,
+    reconnect: Duration,
+    clock: Option<TimeControl>,
+) -> Result<(), String> {
+    let bind = |addr: &str| {
+        TcpListener::bind(addr).map_err(|e| format!("failed to listen on {}: {}", addr, e))
+    };
+    let listener = bind(addr)?;
+    let local = listener.local_addr().map_err(|e| e.to_string())?;
+    println!(
+        "Listening on {}. Join with `othello connect {}`.",
+        local, local
+    );
+    let websocket = match websocket {
+        Some(addr) => {
+            let listener = bind(addr)?;
+            let local = listener.local_addr().map_err(|e| e.to_string())?;
+            println!("WebSocket clients can connect to ws://{}.", local);
+            Some(listener)
+        }
+        None => None,
+    };
+    run(listener, websocket, reconnect, clock, config::dir());
+    Ok(())
+}
+
+// Hosts games on the listeners for as long as they take connections.
+pub fn run(
+    listener: TcpListener,
+    websocket: Option<TcpListener>,
+    reconnect: Duration,
+    clock: Option<TimeControl>,
+    records: Option<PathBuf>,
+) {
+    let (tx, events) = mpsc::channel();
+    if let Some(listener) = websocket {
+        websocket::accept(listener, tx.clone());
+    }
+    accept(listener, tx);
+    let mut server = Server {
+        conns: HashMap::new(),
+        users: HashMap::new(),
+        logins: HashMap::new(),
+        games: BTreeMap::new(),
+        next_game: 1,
+        reconnect,
+        clock,
+        records,
+    };
+    loop {
+        let event = match server.deadline() {
+            None => events.recv().ok(),
+            Some(deadline) => {
+                match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => {
+                        server.expire();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            }
+        };
+        match event {
+            Some(event) => server.handle(event),
+            None => break,
+        }
+    }
+}
+
+// Accepts connections in the background, with a thread reading lines
+// from each of them and another writing to it.
+fn accept(listener: TcpListener, tx: Sender<Event>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(_) => continue,
+            };
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                continue;
+            }
+            let id = next_conn();
+            let (out, outbox) = mpsc::channel();
+            if tx.send(Event::Connected(id, out)).is_err() {
+                break;
+            }
+            thread::spawn(move || write_lines(stream, outbox));
+            let tx = tx.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    let sent = line.map(|line| tx.send(Event::Line(id, line)));
+                    if !matches!(sent, Ok(Ok(()))) {
+                        break;
+                    }
+                }
+                let _ = tx.send(Event::Closed(id));
+            });
+        }
+    });
+}
+
+// Writes the server's messages to one connection, until the server is
+// done with it or it stops taking them. Shutting the socket down ends
+// the reader too, which tells the server the connection closed.
+fn write_lines(mut stream: TcpStream, outbox: Receiver<ServerMsg>) {
+    for msg in outbox {
+        if writeln!(stream, "{}", msg).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+// Counts a finished game towards both players' profiles in `dir`, unless
+// they share a name, and keeps it in the archive there. Returns how the
+// ratings moved.
+fn record(
+    dir: &Path,
+    [black, white]: &[String; 2],
+    outcome: &Outcome,
+    moves: &[Move],
+) -> Result<Option<String>, String> {
+    let mut entry = Entry::new(black, white, outcome, setup::start(), moves)?;
+    archive::append(&dir.join(archive::FILE), &mut entry)?;
+    if black == white {
+        return Ok(None);
+    }
+    let path = dir.join(profiles::FILE);
+    let mut profiles = Profiles::load(&path)?;
+    let [b, w] = profiles.record(black, white, outcome);
+    profiles.save(&path)?;
+    Ok(Some(format!("Ratings: {} {}, {} {}", black, b, white, w)))
+}
+
+fn new_token() -> String {
+    let mut rng = rand::thread_rng();
+    (0..16)
+        .map(|_| format!("{:x}", rng.gen_range(0..16)))
+        .collect()
+}
+
+fn side(index: usize) -> Turn {
+    [Turn::Black, Turn::White][index]
+}
+
+fn seat(side: Turn) -> usize {
+    match side {
+        Turn::White => 1,
+        _ => 0,
+    }
+}
+
+impl Room {
+    fn started(&self) -> bool {
+        self.seats.iter().all(Option::is_some)
+    }
+
+    // Everyone who hears about the game, players first.
+    fn members(&self) -> Vec<String> {
+        let seats = self.seats.iter().flatten().cloned();
+        seats.chain(self.watchers.iter().cloned()).collect()
+    }
+}
+
+impl Server {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Connected(conn, tx) => {
+                self.conns.insert(conn, tx);
+            }
+            Event::Line(_, line) if line.trim().is_empty() => {}
+            Event::Line(conn, line) => match line.parse::<ClientMsg>() {
+                Ok(msg) => self.receive(conn, msg),
+                Err(e) => self.send(conn, &ServerMsg::Error(e)),
+            },
+            Event::Msg(conn, msg) => self.receive(conn, msg),
+            Event::Closed(conn) => self.disconnect(conn),
+        }
+    }
+
+    fn receive(&mut self, conn: usize, msg: ClientMsg) {
+        let token = match (&msg, self.logins.get(&conn).cloned()) {
+            (ClientMsg::Hello { name, token }, None) => {
+                let (name, token) = (name.clone(), token.clone());
+                return self.hello(conn, &name, token);
+            }
+            (ClientMsg::Hello { .. }, Some(_)) => {
+                return self.error(conn, "you are already logged in");
+            }
+            (_, None) => return self.error(conn, "log in with `hello <name>` first"),
+            (_, Some(token)) => token,
+        };
+        let place = self.users[&token].place;
+        let result = match (msg, place) {
+            (ClientMsg::List, _) => {
+                self.list(conn);
+                Ok(())
+            }
+            (ClientMsg::Say(text), _) => {
+                self.say(&token, text);
+                Ok(())
+            }
+            (ClientMsg::Moves, Place::Playing(id, _) | Place::Watching(id)) => {
+                let moves = ServerMsg::Moves(self.games[&id].table.moves());
+                self.send(conn, &moves);
+                Ok(())
+            }
+            (ClientMsg::Moves, Place::Lobby) => Err("you are not in a game".to_string()),
+            (ClientMsg::Seek(side), Place::Lobby) => {
+                self.seek(&token, side);
+                Ok(())
+            }
+            (ClientMsg::Join(id), Place::Lobby) => self.join(&token, id),
+            (ClientMsg::Watch(id), Place::Lobby) => self.watch(&token, id),
+            (ClientMsg::Seek(_) | ClientMsg::Join(_) | ClientMsg::Watch(_), _) => {
+                Err("leave this game first".to_string())
+            }
+            (ClientMsg::Leave, place) => self.leave(&token, place),
+            (ClientMsg::Move(row, col), place) => self.play(place, Move::Play(row, col)),
+            (ClientMsg::Pass, place) => self.play(place, Move::Pass),
+            (ClientMsg::Resign, place) => self.play(place, Move::Resign),
+            (ClientMsg::Hello { .. }, _) => unreachable!("answered above"),
+        };
+        if let Err(e) = result {
+            self.error(conn, &e);
+        }
+    }
+
+    fn hello(&mut self, conn: usize, name: &str, token: Option<String>) {
+        let name = protocol::clean_name(name);
+        let token = match token {
+            // Takes back the seat of a player who lost the connection.
+            Some(token) => match self.users.get_mut(&token) {
+                Some(user) if user.conn.is_none() => {
+                    user.conn = Some(conn);
+                    user.left = None;
+                    println!("{} is back.", user.name);
+                    token
+                }
+                Some(_) => return self.refuse(conn, "that player is connected"),
+                None => return self.refuse(conn, "unknown token"),
+            },
+            None => {
+                let token = new_token();
+                println!("{} logged in.", name);
+                self.users.insert(
+                    token.clone(),
+                    User {
+                        name,
+                        conn: Some(conn),
+                        place: Place::Lobby,
+                        left: None,
+                    },
+                );
+                token
+            }
+        };
+        self.logins.insert(conn, token.clone());
+        let user = &self.users[&token];
+        let welcome = ServerMsg::Welcome {
+            name: user.name.clone(),
+            token: token.clone(),
+        };
+        let place = user.place;
+        self.send(conn, &welcome);
+        if let Place::Playing(id, side) = place {
+            self.send(conn, &ServerMsg::Game { id, side });
+            self.catch_up(conn, id);
+            self.tell(id, &ServerMsg::Back(side));
+        }
+    }
+
+    // The open seeks, then the games being played.
+    fn list(&mut self, conn: usize) {
+        let mut msgs = Vec::new();
+        for (&id, room) in &self.games {
+            if room.started() {
+                continue;
+            }
+            let i = room.seats.iter().position(Option::is_some).unwrap_or(0);
+            msgs.push(ServerMsg::Open {
+                id,
+                name: self.name_at(room, i),
+                side: side(i),
+            });
+        }
+        for (&id, room) in &self.games {
+            if room.started() {
+                msgs.push(ServerMsg::Playing {
+                    id,
+                    black: self.name_at(room, 0),
+                    white: self.name_at(room, 1),
+                });
+            }
+        }
+        msgs.push(ServerMsg::Listed);
+        for msg in msgs {
+            self.send(conn, &msg);
+        }
+    }
+
+    fn say(&mut self, token: &str, text: String) {
+        let user = &self.users[token];
+        let chat = ServerMsg::Chat {
+            name: user.name.clone(),
+            text,
+        };
+        match user.place {
+            Place::Playing(id, _) | Place::Watching(id) => self.tell(id, &chat),
+            Place::Lobby => self.tell_lobby(&chat),
+        }
+    }
+
+    fn seek(&mut self, token: &str, side: Turn) {
+        let id = self.next_game;
+        self.next_game += 1;
+        let mut seats = [None, None];
+        seats[seat(side)] = Some(token.to_string());
+        self.games.insert(
+            id,
+            Room {
+                table: Table::new(self.clock),
+                seats,
+                watchers: Vec::new(),
+            },
+        );
+        let user = self.users.get_mut(token).expect("a user");
+        user.place = Place::Playing(id, side);
+        let name = user.name.clone();
+        println!("{} opened game {}.", name, id);
+        self.tell(id, &ServerMsg::Game { id, side });
+        self.tell(id, &ServerMsg::Waiting);
+        self.tell_lobby(&ServerMsg::Open { id, name, side });
+    }
+
+    fn join(&mut self, token: &str, id: u32) -> Result<(), String> {
+        let room = self.games.get_mut(&id);
+        let i = match room
+            .as_ref()
+            .map(|room| room.seats.iter().position(Option::is_none))
+        {
+            Some(Some(i)) => i,
+            Some(None) => return Err(format!("game {} has started", id)),
+            None => return Err(format!("there is no game {}", id)),
+        };
+        let room = room.expect("a room");
+        room.seats[i] = Some(token.to_string());
+        room.table.start();
+        self.users.get_mut(token).expect("a user").place = Place::Playing(id, side(i));
+        println!(
+            "Game {}: {} vs {}.",
+            id,
+            self.name_in(id, 0),
+            self.name_in(id, 1)
+        );
+        self.tell_lobby(&ServerMsg::Closed(id));
+        self.send_to(token, &ServerMsg::Game { id, side: side(i) });
+        for conn in self.conns_in(id) {
+            self.catch_up(conn, id);
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self, token: &str, id: u32) -> Result<(), String> {
+        let room = self
+            .games
+            .get_mut(&id)
+            .ok_or_else(|| format!("there is no game {}", id))?;
+        room.watchers.push(token.to_string());
+        let started = room.started();
+        self.users.get_mut(token).expect("a user").place = Place::Watching(id);
+        let game = ServerMsg::Game {
+            id,
+            side: Turn::Neither,
+        };
+        self.send_to(token, &game);
+        match self.users[token].conn {
+            Some(conn) if started => self.catch_up(conn, id),
+            _ => self.send_to(token, &ServerMsg::Waiting),
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, token: &str, place: Place) -> Result<(), String> {
+        match place {
+            Place::Lobby => return Err("you are not in a game".to_string()),
+            Place::Playing(id, _) if self.games[&id].started() => {
+                return Err("resign to leave a game".to_string())
+            }
+            Place::Playing(id, _) => self.close(id),
+            Place::Watching(id) => {
+                let room = self.games.get_mut(&id).expect("a room");
+                room.watchers.retain(|watcher| watcher != token);
+            }
+        }
+        self.users.get_mut(token).expect("a user").place = Place::Lobby;
+        Ok(())
+    }
+
+    fn play(&mut self, place: Place, mv: Move) -> Result<(), String> {
+        let (id, side) = match place {
+            Place::Playing(id, side) => (id, side),
+            _ => return Err("you are not playing a game".to_string()),
+        };
+        let room = self.games.get_mut(&id).expect("a room");
+        if !room.started() {
+            return Err("waiting for an opponent".to_string());
+        }
+        let msgs = room.table.play(side, mv)?;
+        let over = room.table.outcome.is_some();
+        for msg in &msgs {
+            self.tell(id, msg);
+        }
+        if over {
+            self.end(id);
+        }
+        Ok(())
+    }
+
+    // Everything a player or watcher needs to pick up the game from here.
+    fn catch_up(&mut self, conn: usize, id: u32) {
+        let players = ServerMsg::Players {
+            black: self.name_in(id, 0),
+            white: self.name_in(id, 1),
+        };
+        let table = &self.games[&id].table;
+        let mut msgs = vec![
+            players,
+            ServerMsg::Board(table.position()),
+            ServerMsg::Turn(table.turn),
+        ];
+        msgs.extend(table.times());
+        for msg in msgs {
+            self.send(conn, &msg);
+        }
+    }
+
+    fn disconnect(&mut self, conn: usize) {
+        self.conns.remove(&conn);
+        let token = match self.logins.remove(&conn) {
+            Some(token) => token,
+            None => return,
+        };
+        let place = self.users[&token].place;
+        match place {
+            // A player in a game has a while to come back.
+            Place::Playing(id, side) if self.games[&id].started() => {
+                let user = self.users.get_mut(&token).expect("a user");
+                user.conn = None;
+                user.left = Some(Instant::now());
+                println!("{} lost the connection.", user.name);
+                let away = ServerMsg::Away {
+                    side,
+                    seconds: self.reconnect.as_secs(),
+                };
+                self.tell(id, &away);
+            }
+            _ => {
+                // Nobody is waiting on anyone else, so they are gone for good.
+                let _ = self.leave(&token, place);
+                let user = self.users.remove(&token).expect("a user");
+                println!("{} logged out.", user.name);
+            }
+        }
+    }
+
+    // The next time someone runs out of time, either to come back after
+    // losing the connection or on the clock.
+    fn deadline(&self) -> Option<Instant> {
+        let left = self.users.values().filter_map(|user| user.left);
+        let left = left.map(|left| left + self.reconnect);
+        let flags = self.games.values().filter_map(|room| room.table.flag);
+        left.chain(flags).min()
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let ids = self.games.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            let room = self.games.get_mut(&id).expect("a room");
+            if let Some(msg) = room.table.check_flag(now) {
+                self.tell(id, &msg);
+                self.end(id);
+            }
+        }
+        let gone = self
+            .users
+            .values()
+            .filter(|user| user.left.is_some_and(|left| now >= left + self.reconnect))
+            .map(|user| user.place)
+            .collect::<Vec<_>>();
+        for place in gone {
+            if let Place::Playing(id, side) = place {
+                if let Some(room) = self.games.get_mut(&id) {
+                    let msg = room.table.finish(End::Forfeit, side.opponent());
+                    self.tell(id, &msg);
+                    self.end(id);
+                }
+            }
+        }
+    }
+
+    // Sends everyone at a finished game back to the lobby, and lets go of
+    // players who aren't there.
+    fn end(&mut self, id: u32) {
+        let room = self.games.remove(&id).expect("a room");
+        if let Some(outcome) = room.table.outcome {
+            let over = ServerMsg::Over(outcome).to_string();
+            println!("Game {} over: {}.", id, over.trim_start_matches("over "));
+            if let Some(dir) = &self.records {
+                let names = [self.name_at(&room, 0), self.name_at(&room, 1)];
+                match record(dir, &names, &outcome, &room.table.history) {
+                    Ok(Some(ratings)) => println!("{}", ratings),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Failed to record game {}: {}", id, e),
+                }
+            }
+        }
+        for token in room.members() {
+            match self.users.get_mut(&token) {
+                Some(user) if user.conn.is_some() => user.place = Place::Lobby,
+                Some(_) => {
+                    self.users.remove(&token);
+                }
+                None => {}
+            }
+        }
+    }
+
+    // Takes back a seek that nobody has joined.
+    fn close(&mut self, id: u32) {
+        let room = self.games.remove(&id).expect("a room");
+        for token in room.watchers {
+            self.send_to(&token, &ServerMsg::Closed(id));
+            if let Some(user) = self.users.get_mut(&token) {
+                user.place = Place::Lobby;
+            }
+        }
+        println!("Game {} was taken back.", id);
+        self.tell_lobby(&ServerMsg::Closed(id));
+    }
+
+    fn name_at(&self, room: &Room, i: usize) -> String {
+        let user = room.seats[i]
+            .as_ref()
+            .and_then(|token| self.users.get(token));
+        user.map_or_else(|| "?".to_string(), |user| user.name.clone())
+    }
+
+    fn name_in(&self, id: u32, i: usize) -> String {
+        self.name_at(&self.games[&id], i)
+    }
+
+    // The connections of everyone at game `id`.
+    fn conns_in(&self, id: u32) -> Vec<usize> {
+        let members = self.games.get(&id).map(Room::members).unwrap_or_default();
+        let users = members.iter().filter_map(|token| self.users.get(token));
+        users.filter_map(|user| user.conn).collect()
+    }
+
+    fn send(&mut self, conn: usize, msg: &ServerMsg) {
+        // A connection that can't be written to is closing, and its
+        // reader will say so.
+        if let Some(tx) = self.conns.get(&conn) {
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    fn send_to(&mut self, token: &str, msg: &ServerMsg) {
+        if let Some(conn) = self.users.get(token).and_then(|user| user.conn) {
+            self.send(conn, msg);
+        }
+    }
+
+    // Tells everyone at game `id`.
+    fn tell(&mut self, id: u32, msg: &ServerMsg) {
+        for conn in self.conns_in(id) {
+            self.send(conn, msg);
+        }
+    }
+
+    fn tell_lobby(&mut self, msg: &ServerMsg) {
+        let lobby = self
+            .users
+            .values()
+            .filter(|user| user.place == Place::Lobby);
+        let conns = lobby.filter_map(|user| user.conn).collect::<Vec<_>>();
+        for conn in conns {
+            self.send(conn, msg);
+        }
+    }
+
+    fn error(&mut self, conn: usize, text: &str) {
+        self.send(conn, &ServerMsg::Error(text.to_string()));
+    }
+
+    fn refuse(&mut self, conn: usize, reason: &str) {
+        self.error(conn, reason);
+        // Its thread closes the socket once the channel is gone.
+        self.conns.remove(&conn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    struct Player {
+        stream: TcpStream,
+        lines: BufReader<TcpStream>,
+    }
+
+    impl Player {
+        fn join(addr: SocketAddr, hello: &str) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let lines = BufReader::new(stream.try_clone().unwrap());
+            let mut player = Player { stream, lines };
+            player.say(hello);
+            player
+        }
+
+        fn say(&mut self, line: &str) {
+            writeln!(self.stream, "{}", line).unwrap();
+        }
+
+        // Reads up to the first line starting with `prefix`, and returns it.
+        fn expect(&mut self, prefix: &str) -> String {
+            loop {
+                let mut line = String::new();
+                assert!(
+                    self.lines.read_line(&mut line).unwrap() > 0,
+                    "no `{}`",
+                    prefix
+                );
+                if line.starts_with(prefix) {
+                    return line.trim().to_string();
+                }
+            }
+        }
+    }
+
+    fn start(reconnect: Duration) -> SocketAddr {
+        start_with_clock(reconnect, None)
+    }
+
+    fn start_with_clock(reconnect: Duration, clock: Option<TimeControl>) -> SocketAddr {
+        start_with(reconnect, clock, None)
+    }
+
+    fn start_with(
+        reconnect: Duration,
+        clock: Option<TimeControl>,
+        records: Option<PathBuf>,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || run(listener, None, reconnect, clock, records));
+        addr
+    }
+
+    fn last_word(line: &str) -> String {
+        line.split_whitespace().last().unwrap().to_string()
+    }
+
+    // `black` opens a game, and `white` joins it. Returns the game's
+    // number and white's token.
+    fn pair(addr: SocketAddr, black: &str, white: &str) -> (Player, Player, String, String) {
+        let mut b = Player::join(addr, &format!("hello {}", black));
+        b.expect("welcome");
+        b.say("seek");
+        let id = b
+            .expect("game")
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .to_string();
+        b.expect("waiting");
+        let mut w = Player::join(addr, &format!("hello {}", white));
+        let token = last_word(&w.expect("welcome"));
+        w.say(&format!("join {}", id));
+        w.expect(&format!("game {} white", id));
+        w.expect(&format!("players {} {}", black, white));
+        b.expect(&format!("players {} {}", black, white));
+        b.expect("turn black");
+        (b, w, id, token)
+    }
+
+    #[test]
+    fn plays_a_game_over_localhost() {
+        let addr = start(Duration::from_secs(5));
+        let (mut black, mut white, id, token) = pair(addr, "alice", "bob");
+
+        white.say("move d3");
+        white.expect("error it is not your turn");
+        black.say("move a1");
+        black.expect("error no discs flipped");
+        black.say("move d3");
+        white.expect("played black d3");
+        white.expect("turn white");
+
+        // White drops out, and comes back with the token.
+        drop(white);
+        black.expect("away white 5");
+        let mut white = Player::join(addr, &format!("hello bob {}", token));
+        white.expect("welcome bob");
+        white.expect(&format!("game {} white", id));
+        let board = white.expect("board");
+        assert!(board.ends_with(" O"), "{}", board);
+        black.expect("back white");
+
+        white.say("resign");
+        black.expect("over resigned black 4 1");
+        // Both are back in the lobby, and can start another game.
+        black.say("seek white");
+        black.expect("game");
+    }
+
+    #[test]
+    fn records_finished_games() {
+        let dir = std::env::temp_dir().join(format!("othello-server-{}", std::process::id()));
+        let addr = start_with(Duration::from_secs(5), None, Some(dir.clone()));
+        let (mut black, mut white, _, _) = pair(addr, "alice", "bob");
+        black.say("move d3");
+        white.expect("turn white");
+        white.say("move c3");
+        black.expect("turn black");
+        black.say("resign");
+        white.expect("over resigned white");
+        // Anything said after the game is answered after it was recorded.
+        white.say("list");
+        white.expect("listed");
+
+        let games = archive::load(&dir.join(archive::FILE)).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(
+            (games[0].black.as_str(), games[0].white.as_str()),
+            ("alice", "bob")
+        );
+        assert_eq!(
+            (games[0].winner.as_str(), games[0].end.as_str()),
+            ("white", "resigned")
+        );
+        assert_eq!(games[0].moves, "d3 c3");
+        let profiles = Profiles::load(&dir.join(profiles::FILE)).unwrap();
+        assert_eq!(profiles.players["alice"].losses, 1);
+        assert_eq!(profiles.players["bob"].wins, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forfeits_a_player_who_does_not_come_back() {
+        let addr = start(Duration::from_millis(100));
+        let (mut black, white, _, _) = pair(addr, "alice", "bob");
+        drop(white);
+        black.expect("over forfeit black");
+    }
+
+    #[test]
+    fn flags_a_player_who_runs_out_of_time() {
+        // 0.005 minutes, or 300 milliseconds each.
+        let addr = start_with_clock(Duration::from_secs(5), "0.005+0".parse().ok());
+        let (mut black, mut white, _, _) = pair(addr, "alice", "bob");
+        black.expect("clock");
+        black.say("move d3");
+        white.expect("clock");
+        white.expect("over time black 4 1");
+    }
+
+    #[test]
+    fn keeps_playing_while_a_client_stops_reading() {
+        let addr = start(Duration::from_secs(5));
+        let (mut alice, mut bob, _, _) = pair(addr, "alice", "bob");
+
+        // Mallory chats to the lobby, and to herself, far more than any
+        // socket buffer holds, and never reads a word of it.
+        let mut mallory = Player::join(addr, "hello mallory");
+        let chat = format!("say {}\n", "x".repeat(4000));
+        for _ in 0..5000 {
+            mallory.stream.write_all(chat.as_bytes()).unwrap();
+        }
+        alice.say("move d3");
+        bob.expect("played black d3");
+        bob.say("move c3");
+        alice.expect("played white c3");
+        alice.say("say still here");
+        bob.expect("chat alice still here");
+    }
+
+    #[test]
+    fn hosts_games_side_by_side_with_spectators() {
+        let addr = start(Duration::from_secs(5));
+        let (mut alice, mut bob, first, _) = pair(addr, "alice", "bob");
+        let (mut carol, _dave, second, _) = pair(addr, "carol", "dave");
+        assert_ne!(first, second);
+
+        let mut eve = Player::join(addr, "hello eve");
+        eve.expect("welcome eve");
+        eve.say(&format!("join {}", first));
+        eve.expect(&format!("error game {} has started", first));
+        eve.say("list");
+        eve.expect(&format!("playing {} alice bob", first));
+        eve.expect(&format!("playing {} carol dave", second));
+        eve.expect("listed");
+        eve.say(&format!("watch {}", first));
+        eve.expect(&format!("game {} spectator", first));
+        eve.expect("players alice bob");
+        eve.say("move d3");
+        eve.expect("error you are not playing a game");
+
+        // Moves and chat stay in their own game.
+        carol.say("move c4");
+        alice.say("move d3");
+        eve.expect("played black d3");
+        bob.say("say good luck");
+        alice.expect("chat bob good luck");
+        eve.expect("chat bob good luck");
+        carol.say("say hi");
+        eve.say("leave");
+        eve.say("say anyone here?");
+        eve.expect("chat eve anyone here?");
+        alice.say("say bye");
+        alice.expect("chat alice bye");
+        let mut line = String::new();
+        eve.stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        assert!(
+            eve.lines.read_line(&mut line).is_err(),
+            "eve heard `{}`",
+            line
+        );
+    }
+}
@@ -0,0 +1,503 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use rand::seq::SliceRandom;
+
+use crate::board::{self, Board, Cell};
+use crate::config;
+use crate::error::InputError;
+use crate::events::{self, Move, Turn};
+use crate::theme;
+
+static START: OnceLock<Setup> = OnceLock::new();
+
+// Where games start from: the usual four discs, a random eight-disc
+// start, or a position string.
+#[derive(Clone)]
+pub enum Start {
+    Standard,
+    Random,
+    Position(String),
+}
+
+impl FromStr for Start {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Start::Standard),
+            "random" => Ok(Start::Random),
+            _ => Ok(Start::Position(s.to_string())),
+        }
+    }
+}
+
+// Squares taken out of play before the game starts: none, a few picked
+// at random, or a list of squares like `a1 h8`.
+#[derive(Clone)]
+pub enum Holes {
+    None,
+    Random,
+    Squares(String),
+}
+
+impl FromStr for Holes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Holes::None),
+            "random" => Ok(Holes::Random),
+            _ => Ok(Holes::Squares(s.to_string())),
+        }
+    }
+}
+
+// A position to start a game from.
+#[derive(Clone)]
+pub struct Setup {
+    pub board: Board,
+    pub turn: Turn,
+}
+
+impl Setup {
+    pub fn new(start: &Start, holes: &Holes, size: usize) -> Result<Self, String> {
+        let mut setup = match start {
+            Start::Position(text) => Self::parse(text)?,
+            _ => Self::standard(size),
+        };
+        setup.fits(size)?;
+        setup.block(holes)?;
+        // Random moves are played around the holes.
+        if let Start::Random = start {
+            setup.play_randomly(4);
+        }
+        setup.check()?;
+        Ok(setup)
+    }
+
+    // Makes sure the position is on a board of the size being played.
+    pub fn fits(&self, size: usize) -> Result<(), String> {
+        if self.board.size == size {
+            return Ok(());
+        }
+        Err(format!(
+            "the position is {}x{}, but the board is {}x{} (see `--size`)",
+            self.board.size, self.board.size, size, size
+        ))
+    }
+
+    pub fn is_standard(&self) -> bool {
+        self.to_string() == Self::standard(self.board.size).to_string()
+    }
+
+    pub fn standard(size: usize) -> Self {
+        Self {
+            board: Board::with_size(size),
+            turn: Turn::Black,
+        }
+    }
+
+    // One of the eight-disc starts engines are tested from: the usual
+    // start with four random moves played, so Black is to move again.
+    pub fn random(size: usize) -> Self {
+        let mut setup = Self::standard(size);
+        setup.play_randomly(4);
+        setup
+    }
+
+    fn play_randomly(&mut self, plies: usize) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..plies {
+            let moves = Move::legal_moves(&self.board, self.turn);
+            let Some(&(row, col)) = moves.choose(&mut rng) else {
+                break;
+            };
+            self.board = Move::after(&self.board, self.turn, row, col).expect("legal move");
+            self.turn = self.turn.opponent();
+        }
+        self.board.cells[0][0] = Cell::Indicator(self.turn);
+    }
+
+    // Blocks the squares `holes` asks for. Random holes come in fours, each
+    // square turned a quarter round the board from the last, and stay out
+    // of the middle sixteen squares so the opening plays as usual.
+    fn block(&mut self, holes: &Holes) -> Result<(), String> {
+        let size = self.board.size;
+        let squares = match holes {
+            Holes::None => Vec::new(),
+            Holes::Squares(text) => {
+                let notation = config::current().notation;
+                let squares = notation
+                    .parse(&text.split_whitespace().collect::<String>(), size)
+                    .ok_or_else(|| format!("`{}` is not a list of squares", text))?;
+                if let Some(&(row, col)) = squares
+                    .iter()
+                    .find(|&&(r, c)| is_disc(&self.board.cells[r][c]))
+                {
+                    return Err(format!("{} has a disc on it", notation.format(row, col)));
+                }
+                squares
+            }
+            Holes::Random => {
+                let mid = size / 2;
+                let middle = |i: usize| (mid - 1..=mid + 2).contains(&i);
+                let mut orbits = Vec::new();
+                for row in 1..=mid {
+                    for col in 1..=mid {
+                        let orbit = [
+                            (row, col),
+                            (col, size + 1 - row),
+                            (size + 1 - row, size + 1 - col),
+                            (size + 1 - col, row),
+                        ];
+                        let open = orbit
+                            .iter()
+                            .all(|&(r, c)| !is_disc(&self.board.cells[r][c]));
+                        if open && !(middle(row) && middle(col)) {
+                            orbits.push(orbit);
+                        }
+                    }
+                }
+                let mut rng = rand::thread_rng();
+                let orbits = orbits.choose_multiple(&mut rng, size / 4);
+                orbits.flatten().copied().collect()
+            }
+        };
+        for (row, col) in squares {
+            self.board.set(row, col, Cell::Blocked);
+        }
+        Ok(())
+    }
+
+    // Reads a position string and makes sure it could come up in a game.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let setup = Self::read(text)?;
+        setup.check()?;
+        Ok(setup)
+    }
+
+    // Reads a position string: every square row by row, `X` for black, `O`
+    // for white, `-` for empty and `#` for a hole, then `X` or `O` for who
+    // is to move.
+    // Spaces, and `/` between rows, are allowed and ignored, and so are
+    // the other characters Edax takes: `*` or `b` for black, `w` for white
+    // and `.` for empty.
+    pub fn read(text: &str) -> Result<Self, String> {
+        let chars = squares(text);
+        let (side, squares) = chars.split_last().ok_or("empty position")?;
+        let turn = match cell(*side) {
+            Some(Cell::Black) => Turn::Black,
+            Some(Cell::White) => Turn::White,
+            _ => return Err("a position ends with who is to move, `X` or `O`".to_string()),
+        };
+        let size =
+            size_of(text).ok_or_else(|| format!("{} squares don't make a board", squares.len()))?;
+
+        let mut board = Board::empty(size);
+        for (i, &c) in squares.iter().enumerate() {
+            board.cells[i / size + 1][i % size + 1] =
+                cell(c).ok_or_else(|| format!("`{}` is not `X`, `O`, `-` or `#`", c))?;
+        }
+        board.validate_cells();
+        board.cells[0][0] = Cell::Indicator(turn);
+        Ok(Self { board, turn })
+    }
+
+    // Writes the position the way `parse` reads it, and Edax prints it:
+    // one line, like `---------------------------OX------XO--------------------------- X`.
+    pub fn format(&self) -> String {
+        let mut text = String::new();
+        for row in 1..=self.board.size {
+            for col in 1..=self.board.size {
+                text.push(match self.board.cells[row][col] {
+                    Cell::Black => 'X',
+                    Cell::White => 'O',
+                    Cell::Blocked => '#',
+                    _ => '-',
+                });
+            }
+        }
+        text.push_str(if self.turn == Turn::White { " O" } else { " X" });
+        text
+    }
+
+    // Whether the position could come up in a game: the middle four
+    // squares are never empty, every disc was placed next to another,
+    // and whoever is to move has a move to make.
+    pub fn check(&self) -> Result<(), String> {
+        let board = &self.board;
+        let mid = board.size / 2;
+        let middle = [
+            (mid, mid),
+            (mid, mid + 1),
+            (mid + 1, mid),
+            (mid + 1, mid + 1),
+        ];
+        if middle.iter().any(|&(r, c)| !is_disc(&board.cells[r][c])) {
+            return Err("the middle four squares must have discs on them".to_string());
+        }
+
+        // Spread out from the middle, disc to touching disc.
+        let mut seen = vec![vec![false; board.size + 1]; board.size + 1];
+        let mut stack = vec![(mid, mid)];
+        seen[mid][mid] = true;
+        while let Some((row, col)) = stack.pop() {
+            let cols = col.max(2) - 1..=(col + 1).min(board.size);
+            let rows = row.max(2) - 1..=(row + 1).min(board.size);
+            for (r, c) in rows.flat_map(|r| cols.clone().map(move |c| (r, c))) {
+                if !seen[r][c] && is_disc(&board.cells[r][c]) {
+                    seen[r][c] = true;
+                    stack.push((r, c));
+                }
+            }
+        }
+        let (black, white) = board.count_discs();
+        let reached = seen.iter().flatten().filter(|&&seen| seen).count();
+        if reached != black as usize + white as usize {
+            return Err("every disc must touch another one".to_string());
+        }
+
+        if !Move::has_moves(board, self.turn) {
+            return Err(if Move::has_moves(board, self.turn.opponent()) {
+                format!("{} has no move here", side(self.turn))
+            } else {
+                "nobody has a move here".to_string()
+            });
+        }
+        Ok(())
+    }
+
+    fn toggle_turn(&mut self) {
+        self.turn = self.turn.opponent();
+        self.board.cells[0][0] = Cell::Indicator(self.turn);
+    }
+}
+
+impl fmt::Display for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format())
+    }
+}
+
+impl FromStr for Setup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+// The characters of a position string that matter, squares then side.
+fn squares(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|c| !c.is_whitespace() && *c != '/')
+        .collect()
+}
+
+// How many squares across a position string's board is, if it is one.
+pub fn size_of(text: &str) -> Option<usize> {
+    let squares = squares(text).len().checked_sub(1)?;
+    (board::MIN_SIZE..=board::MAX_SIZE)
+        .step_by(2)
+        .find(|size| size * size == squares)
+}
+
+// A square of a position string; empty squares read as `Illegal` until
+// the board works out which are legal moves.
+fn cell(c: char) -> Option<Cell> {
+    match c {
+        'X' | 'x' | '*' | 'B' | 'b' => Some(Cell::Black),
+        'O' | 'o' | 'W' | 'w' => Some(Cell::White),
+        '-' | '.' => Some(Cell::Illegal),
+        '#' => Some(Cell::Blocked),
+        _ => None,
+    }
+}
+
+fn is_disc(cell: &Cell) -> bool {
+    matches!(cell, Cell::Black | Cell::White)
+}
+
+pub fn side(turn: Turn) -> &'static str {
+    if turn == Turn::White {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+pub fn init(setup: Setup) {
+    let _ = START.set(setup);
+}
+
+// Where this run's games start from.
+pub fn start() -> &'static Setup {
+    START.get_or_init(|| Setup::standard(config::current().size))
+}
+
+// Splits a position string off the top of a saved game, if there is one.
+pub fn split(text: &str) -> Result<(Option<Setup>, &str), String> {
+    let text = text.trim_start();
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+    // Moves are never more than a few characters long, and positions are.
+    if first.trim().len() <= board::MIN_SIZE * board::MIN_SIZE {
+        return Ok((None, text));
+    }
+    match Setup::read(first) {
+        Ok(setup) => setup.check().map(|()| (Some(setup), rest)),
+        Err(_) => Ok((None, text)),
+    }
+}
+
+// Lets the player put together a position square by square, starting
+// from `setup`. Returns `None` if they quit instead.
+pub fn edit(mut setup: Setup) -> Result<Option<Setup>, InputError> {
+    let notation = config::current().notation;
+    let example = notation.format(4, 3);
+    let mut notice: Option<String> = None;
+    loop {
+        setup.board.draw(setup.turn, None, None);
+        println!("{}", setup);
+        if let Some(notice) = notice.take() {
+            println!("{}", theme::current().error.paint(&notice));
+        }
+        println!(
+            "Enter squares to cycle them through black, white and empty. (Example: {})",
+            example
+        );
+        println!(
+            "Enter `b`, `w`, `e` or `#` before squares to fill, empty or block them. (Example: b {})",
+            example
+        );
+        println!("Enter `t` to change who is to move, `c` to clear the board,");
+        println!("`s` for the usual start, `x` for a random one or `l` and a position string.");
+        println!("Enter `d` when done, or `q` to quit.");
+
+        let line = match events::read_line(None)? {
+            Some(line) => line,
+            None => continue,
+        };
+        let mut words = line.split_whitespace();
+        let size = setup.board.size;
+        match words.next() {
+            None => {}
+            Some("d") => match setup.check() {
+                Ok(()) => return Ok(Some(setup)),
+                Err(e) => notice = Some(format!("Not a legal position: {}.", e)),
+            },
+            Some("q") => return Ok(None),
+            Some("t") => setup.toggle_turn(),
+            Some("c") => setup.board = Board::empty(size),
+            Some("s") => setup = Setup::standard(size),
+            Some("x") => setup = Setup::random(size),
+            Some("l") => match Setup::read(&words.collect::<String>()) {
+                Ok(loaded) if loaded.board.size == size => setup = loaded,
+                Ok(_) => notice = Some(format!("That position isn't {}x{}.", size, size)),
+                Err(e) => notice = Some(format!("Invalid position: {}.", e)),
+            },
+            Some(first) => {
+                let (fill, squares) = match first {
+                    "b" => (Some(Cell::Black), words.collect::<String>()),
+                    "w" => (Some(Cell::White), words.collect::<String>()),
+                    "e" => (Some(Cell::Illegal), words.collect::<String>()),
+                    "#" => (Some(Cell::Blocked), words.collect::<String>()),
+                    _ => (None, line.split_whitespace().collect::<String>()),
+                };
+                match notation.parse(&squares, size) {
+                    Some(squares) if !squares.is_empty() => {
+                        for (row, col) in squares {
+                            let cell =
+                                fill.clone()
+                                    .unwrap_or_else(|| match setup.board.cells[row][col] {
+                                        Cell::Black => Cell::White,
+                                        Cell::White => Cell::Illegal,
+                                        _ => Cell::Black,
+                                    });
+                            setup.board.set(row, col, cell);
+                        }
+                    }
+                    _ => notice = Some(format!("`{}` is not a square or a command.", line.trim())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IllegalMove;
+    use crate::events::InputHandler;
+
+    const START: &str = "---------------------------OX------XO--------------------------- X";
+
+    #[test]
+    fn formats_the_usual_start_like_edax() {
+        assert_eq!(Setup::standard(8).format(), START);
+        assert!(START.parse::<Setup>().unwrap().is_standard());
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let text = "--------/--------/---X----/--XOX---/---XO---/--------/--------/-------- O";
+        let setup = Setup::parse(text).unwrap();
+        assert!(setup.turn == Turn::White);
+        assert_eq!(setup.board.count_discs(), (4, 2));
+        assert_eq!(
+            Setup::parse(&setup.format()).unwrap().format(),
+            setup.format()
+        );
+    }
+
+    #[test]
+    fn reads_the_other_characters_edax_takes() {
+        let edax = "...........................wb......bw........................... b";
+        assert_eq!(Setup::parse(edax).unwrap().format(), START);
+        assert_eq!(size_of("-------------OX---XO------- X"), None);
+        assert_eq!(size_of("-----OX--XO----- O"), Some(4));
+    }
+
+    #[test]
+    fn rejects_positions_that_cannot_come_up() {
+        // Too short, a stray character, and no side to move.
+        assert!(Setup::parse("---OX--XO--- X").is_err());
+        assert!(Setup::parse(&START.replace(" X", " Z")).is_err());
+        assert!(Setup::parse(&START[..64]).is_err());
+        // A disc off on its own, and an empty middle square.
+        assert!(Setup::parse(&format!("X{}", &START[1..])).is_err());
+        assert!(Setup::parse(&START.replacen('O', "-", 1)).is_err());
+        // Nobody can move when every disc is black.
+        assert!(Setup::parse(&START.replace('O', "X")).is_err());
+    }
+
+    #[test]
+    fn holes_stop_lines_like_the_edge_does() {
+        let through = Setup::read("---- XOO- -XO- ---- X").unwrap();
+        assert!(Move::legal_moves(&through.board, Turn::Black).contains(&(2, 4)));
+        let mut walled = Setup::read("---- XO#- -XO- ---- X").unwrap();
+        assert!(!Move::legal_moves(&walled.board, Turn::Black).contains(&(2, 4)));
+        let err = Move::check_move(&mut walled.board, Turn::Black, 2, 3);
+        assert_eq!(err, Err(IllegalMove::Blocked));
+        assert_eq!(walled.board.empties(), 11);
+    }
+
+    #[test]
+    fn random_holes_turn_with_the_board() {
+        let setup = Setup::new(&Start::Standard, &Holes::Random, 8).unwrap();
+        let board = &setup.board;
+        let blocked = |r: usize, c: usize| board.cells[r][c] == Cell::Blocked;
+        let mut holes = 0;
+        for row in 1..=8 {
+            for col in 1..=8 {
+                if blocked(row, col) {
+                    holes += 1;
+                    assert!(blocked(col, 9 - row));
+                    assert!(!(3..=6).contains(&row) || !(3..=6).contains(&col));
+                }
+            }
+        }
+        assert_eq!(holes, 8);
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+use colored::{Color, ColoredString, Colorize};
+use serde::Deserialize;
+
+use crate::config;
+use crate::events::Turn;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+static GLYPHS: OnceLock<Glyphs> = OnceLock::new();
+
+pub const BUILT_IN: [&str; 4] = ["dark", "light", "high-contrast", "monochrome"];
+
+// How one part of the screen is painted. Unset colors are left to the terminal.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    const fn new(fg: Option<Color>, bg: Option<Color>) -> Self {
+        Self {
+            fg,
+            bg,
+            bold: false,
+            reverse: false,
+        }
+    }
+
+    pub fn paint(&self, s: &str) -> ColoredString {
+        let mut painted = s.normal();
+        if let Some(fg) = self.fg {
+            painted = painted.color(fg);
+        }
+        if let Some(bg) = self.bg {
+            painted = painted.on_color(bg);
+        }
+        if self.bold {
+            painted = painted.bold();
+        }
+        if self.reverse {
+            painted = painted.reversed();
+        }
+        painted
+    }
+
+    // Lays a highlight over this style, e.g. the last move over a disc.
+    // Whatever the highlight leaves unset shows through.
+    pub fn under(self, highlight: Style) -> Style {
+        Style {
+            fg: highlight.fg.or(self.fg),
+            bg: highlight.bg.or(self.bg),
+            bold: self.bold || highlight.bold,
+            reverse: self.reverse || highlight.reverse,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub black: Style,
+    pub white: Style,
+    pub empty: Style,
+    pub legal: Style,
+    pub last_move: Style,
+    pub flipped: Style,
+    pub cursor: Style,
+    pub indicator: Style,
+    pub info: Style,
+    pub result: Style,
+    pub error: Style,
+    // Draw White as a hollow `○`, for themes that can't tell the discs apart by color.
+    pub hollow_white: bool,
+}
+
+impl Theme {
+    // The original colors, made for dark terminals.
+    pub fn dark() -> Self {
+        use Color::*;
+        Self {
+            black: Style::new(Some(Black), Some(Green)),
+            white: Style::new(None, Some(Green)),
+            empty: Style::new(None, Some(Green)),
+            legal: Style::new(Some(Yellow), Some(Green)),
+            last_move: Style::new(None, Some(Yellow)),
+            flipped: Style::new(None, Some(BrightGreen)),
+            cursor: Style::new(None, Some(Cyan)),
+            indicator: Style::new(Some(Green), None),
+            info: Style::new(Some(Yellow), None),
+            result: Style::new(Some(Magenta), None),
+            error: Style::new(Some(Red), None),
+            hollow_white: false,
+        }
+    }
+
+    // On a light background the default foreground is dark,
+    // so White has to be painted white explicitly.
+    pub fn light() -> Self {
+        use Color::*;
+        Self {
+            black: Style::new(Some(Black), Some(Green)),
+            white: Style::new(Some(BrightWhite), Some(Green)),
+            empty: Style::new(Some(Black), Some(Green)),
+            legal: Style::new(Some(BrightYellow), Some(Green)),
+            last_move: Style::new(None, Some(Yellow)),
+            flipped: Style::new(None, Some(BrightGreen)),
+            cursor: Style::new(None, Some(Blue)),
+            indicator: Style::new(Some(Green), None),
+            info: Style::new(Some(Blue), None),
+            result: Style::new(Some(Magenta), None),
+            error: Style::new(Some(Red), None),
+            hollow_white: false,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        use Color::*;
+        let bold = |fg, bg| Style {
+            bold: true,
+            ..Style::new(fg, bg)
+        };
+        Self {
+            black: bold(Some(Black), Some(BrightWhite)),
+            white: bold(Some(BrightWhite), Some(Black)),
+            empty: Style::new(Some(BrightBlack), Some(Black)),
+            legal: bold(Some(BrightYellow), Some(Black)),
+            last_move: bold(None, Some(BrightRed)),
+            flipped: bold(None, Some(Blue)),
+            cursor: bold(None, Some(BrightCyan)),
+            indicator: bold(Some(BrightWhite), None),
+            info: bold(Some(BrightYellow), None),
+            result: bold(Some(BrightMagenta), None),
+            error: bold(Some(BrightRed), None),
+            hollow_white: true,
+        }
+    }
+
+    // No colors at all, only bold and reverse video.
+    pub fn monochrome() -> Self {
+        let plain = Style::default();
+        let bold = Style {
+            bold: true,
+            ..plain
+        };
+        let reverse = Style {
+            reverse: true,
+            ..plain
+        };
+        Self {
+            black: plain,
+            white: plain,
+            empty: plain,
+            legal: bold,
+            last_move: reverse,
+            flipped: bold,
+            cursor: reverse,
+            indicator: plain,
+            info: plain,
+            result: bold,
+            error: bold,
+            hollow_white: true,
+        }
+    }
+
+    // Looks `name` up among the built-in themes, then the user's themes file.
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            "dark" => return Ok(Self::dark()),
+            "light" => return Ok(Self::light()),
+            "high-contrast" => return Ok(Self::high_contrast()),
+            "monochrome" => return Ok(Self::monochrome()),
+            _ => {}
+        }
+
+        let mut user = user_themes()?;
+        match user.remove(name) {
+            Some(spec) => spec.resolve(name),
+            None => {
+                let mut names = BUILT_IN.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+                names.extend(user.into_keys());
+                Err(format!(
+                    "unknown theme `{}` (available: {})",
+                    name,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+// The characters discs and empty cells are drawn with.
+// Board cells are two columns wide either way, so the layout doesn't change.
+pub struct Glyphs {
+    pub black: &'static str,
+    pub white: &'static str,
+    pub hollow_white: &'static str,
+    pub empty: &'static str,
+    pub blocked: &'static str,
+    pub legal: &'static str,
+    // One column wide, for scores and clocks.
+    pub black_mark: &'static str,
+    pub white_mark: &'static str,
+    pub to_move: &'static str,
+}
+
+pub const UNICODE: Glyphs = Glyphs {
+    black: "● ",
+    white: "● ",
+    hollow_white: "○ ",
+    empty: "・",
+    blocked: "▒▒",
+    legal: "* ",
+    black_mark: "●",
+    white_mark: "○",
+    to_move: "▶",
+};
+
+// For fonts that misalign the full-width `・`, and for logs.
+pub const ASCII: Glyphs = Glyphs {
+    black: "X ",
+    white: "O ",
+    hollow_white: "O ",
+    empty: ". ",
+    blocked: "# ",
+    legal: "* ",
+    black_mark: "X",
+    white_mark: "O",
+    to_move: ">",
+};
+
+impl Glyphs {
+    pub fn mark(&self, turn: Turn) -> &'static str {
+        match turn {
+            Turn::Black => self.black_mark,
+            Turn::White => self.white_mark,
+            Turn::Neither => " ",
+        }
+    }
+}
+
+// Picks the theme and glyphs for this run, and decides whether to emit
+// escape codes at all.
+//
+// Output that isn't a terminal gets plain ASCII with no escape codes, so that
+// game logs stay readable and diffable. `CLICOLOR_FORCE` brings the colors back.
+// `NO_COLOR` switches to the monochrome theme, unless a theme was asked for by name.
+pub fn init(name: Option<&str>, ascii: bool) -> Result<(), String> {
+    let tty = io::stdout().is_terminal();
+    let forced = env::var("CLICOLOR_FORCE").is_ok_and(|v| v != "0");
+    let no_color = env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
+    colored::control::set_override(forced || tty);
+    let _ = GLYPHS.set(if ascii || !tty { ASCII } else { UNICODE });
+
+    let _ = THEME.set(pick(name, no_color)?);
+    Ok(())
+}
+
+// The theme asked for by name, or else the default, which is monochrome
+// under `NO_COLOR`.
+fn pick(name: Option<&str>, no_color: bool) -> Result<Theme, String> {
+    match name {
+        Some(name) => Theme::named(name),
+        None if no_color => Ok(Theme::monochrome()),
+        None => Ok(Theme::dark()),
+    }
+}
+
+pub fn current() -> &'static Theme {
+    THEME.get_or_init(Theme::dark)
+}
+
+pub fn glyphs() -> &'static Glyphs {
+    GLYPHS.get_or_init(|| UNICODE)
+}
+
+//# User themes:
+//
+// `themes.toml` in the config directory holds one table per theme.
+// Every entry is optional and falls back to the `base` theme, `dark` by default:
+//
+//     [ocean]
+//     base = "light"
+//     black = { fg = "black", bg = "blue" }
+//     white = { fg = "bright white", bg = "blue", bold = true }
+//     hollow_white = false
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeSpec {
+    base: Option<String>,
+    black: Option<StyleSpec>,
+    white: Option<StyleSpec>,
+    empty: Option<StyleSpec>,
+    legal: Option<StyleSpec>,
+    last_move: Option<StyleSpec>,
+    flipped: Option<StyleSpec>,
+    cursor: Option<StyleSpec>,
+    indicator: Option<StyleSpec>,
+    info: Option<StyleSpec>,
+    result: Option<StyleSpec>,
+    error: Option<StyleSpec>,
+    hollow_white: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    bold: bool,
+    #[serde(default)]
+    reverse: bool,
+}
+
+fn user_themes() -> Result<BTreeMap<String, ThemeSpec>, String> {
+    let path = match config::dir() {
+        Some(dir) => dir.join("themes.toml"),
+        None => return Ok(BTreeMap::new()),
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+impl ThemeSpec {
+    fn resolve(self, name: &str) -> Result<Theme, String> {
+        let base = match self.base.as_deref() {
+            None => Theme::dark(),
+            Some(base) if BUILT_IN.contains(&base) => Theme::named(base)?,
+            Some(base) => {
+                return Err(format!(
+                    "theme `{}`: base must be a built-in theme, not `{}`",
+                    name, base
+                ))
+            }
+        };
+        let style = |spec: Option<StyleSpec>, fallback: Style| match spec {
+            Some(spec) => spec.resolve(name),
+            None => Ok(fallback),
+        };
+        Ok(Theme {
+            black: style(self.black, base.black)?,
+            white: style(self.white, base.white)?,
+            empty: style(self.empty, base.empty)?,
+            legal: style(self.legal, base.legal)?,
+            last_move: style(self.last_move, base.last_move)?,
+            flipped: style(self.flipped, base.flipped)?,
+            cursor: style(self.cursor, base.cursor)?,
+            indicator: style(self.indicator, base.indicator)?,
+            info: style(self.info, base.info)?,
+            result: style(self.result, base.result)?,
+            error: style(self.error, base.error)?,
+            hollow_white: self.hollow_white.unwrap_or(base.hollow_white),
+        })
+    }
+}
+
+impl StyleSpec {
+    fn resolve(self, theme: &str) -> Result<Style, String> {
+        let color = |c: Option<String>| match c {
+            None => Ok(None),
+            Some(c) => c
+                .parse::<Color>()
+                .map(Some)
+                .map_err(|_| format!("theme `{}`: unknown color `{}`", theme, c)),
+        };
+        Ok(Style {
+            fg: color(self.fg)?,
+            bg: color(self.bg)?,
+            bold: self.bold,
+            reverse: self.reverse,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(text: &str) -> Result<Theme, String> {
+        let mut specs: BTreeMap<String, ThemeSpec> =
+            toml::from_str(text).map_err(|e| e.to_string())?;
+        specs
+            .remove("test")
+            .expect("a [test] table")
+            .resolve("test")
+    }
+
+    #[test]
+    fn resolves_user_themes_over_their_base() {
+        let theme = spec(
+            "[test]\n\
+             base = \"light\"\n\
+             black = { fg = \"black\", bg = \"blue\" }\n\
+             white = { fg = \"bright white\", bold = true }\n\
+             hollow_white = true\n",
+        )
+        .unwrap();
+        let light = Theme::light();
+        assert_eq!(
+            theme.black,
+            Style::new(Some(Color::Black), Some(Color::Blue))
+        );
+        assert_eq!(theme.white.fg, Some(Color::BrightWhite));
+        assert!(theme.white.bold);
+        assert_eq!(theme.empty, light.empty);
+        assert_eq!(theme.error, light.error);
+        assert!(theme.hollow_white);
+
+        // Without a base, the rest comes from `dark`.
+        let theme = spec("[test]\nlegal = { reverse = true }\n").unwrap();
+        assert!(theme.legal.reverse);
+        assert_eq!(theme.black, Theme::dark().black);
+    }
+
+    #[test]
+    fn rejects_bad_theme_specs() {
+        let err = spec("[test]\nblack = { fg = \"plaid\" }\n").err().unwrap();
+        assert!(err.contains("unknown color `plaid`"), "{}", err);
+        let err = spec("[test]\nbase = \"ocean\"\n").err().unwrap();
+        assert!(err.contains("base must be a built-in theme"), "{}", err);
+        assert!(spec("[test]\nshadow = { fg = \"red\" }\n").is_err());
+        assert!(spec("[test]\nblack = { fg = \"red\", blink = true }\n").is_err());
+    }
+
+    #[test]
+    fn no_color_only_changes_the_default() {
+        let monochrome = Theme::monochrome();
+        assert_eq!(pick(None, true).unwrap().black, monochrome.black);
+        assert_eq!(pick(None, false).unwrap().black, Theme::dark().black);
+        let named = pick(Some("light"), true).unwrap();
+        assert_eq!(named.black, Theme::light().black);
+        assert_eq!(named.legal, Theme::light().legal);
+    }
+
+    #[test]
+    fn highlights_show_the_style_under_them() {
+        let disc = Style::new(Some(Color::Black), Some(Color::Green));
+        let highlight = Style {
+            bg: Some(Color::Yellow),
+            bold: true,
+            ..Style::default()
+        };
+        let both = disc.under(highlight);
+        assert_eq!(both.fg, Some(Color::Black));
+        assert_eq!(both.bg, Some(Color::Yellow));
+        assert!(both.bold);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::board::Board;
+use crate::config;
+use crate::events::{InputHandler, Move, Notation, PlayabilityChecker, Turn};
+use crate::ggf;
+use crate::setup::{self, Setup};
+
+// One position of a game played back from a transcript.
+pub struct Position {
+    pub board: Board,
+    // Whoever is to move, or once the game is over, whoever it ended on.
+    pub turn: Turn,
+    // The move that led here, if any.
+    pub played: Option<Move>,
+    // What to tell the players about it, as in `board::message`.
+    pub mv: Option<Move>,
+}
+
+impl Position {
+    pub fn is_over(&self) -> bool {
+        matches!(self.mv, Some(Move::Win(_, _)))
+    }
+}
+
+// A recorded game: who played it, if that was written down, where it
+// started if not from this run's start, and its moves.
+pub struct Game {
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub start: Option<Setup>,
+    pub moves: Vec<Move>,
+}
+
+// Reads a game saved with `q`, any transcript, or a GGF record.
+pub fn read_game(text: &str, notation: Notation) -> Result<Game, String> {
+    if ggf::is_ggf(text) {
+        return ggf::parse(text);
+    }
+    let (start, text) = setup::split(text)?;
+    Ok(Game {
+        black: None,
+        white: None,
+        start,
+        moves: parse(text, notation)?,
+    })
+}
+
+// Reads a list of moves, written together like `f5d6c3` or apart like
+// `1. f5 d6 2. c3`. Move numbers are skipped. Passes are only needed where
+// a player chose to pass; a forced pass is made without being asked for.
+pub fn parse(text: &str, notation: Notation) -> Result<Vec<Move>, String> {
+    let mut moves = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',') {
+        let lower = token.to_ascii_lowercase();
+        let numbered = token.ends_with('.') && token.trim_end_matches('.').parse::<u32>().is_ok();
+        if token.is_empty() || numbered {
+            continue;
+        }
+        if ["pass", "ps", "--", "skip"].contains(&lower.as_str()) {
+            moves.push(Move::Pass);
+            continue;
+        }
+
+        match notation.parse(token, config::current().size) {
+            Some(squares) => moves.extend(squares.into_iter().map(|(r, c)| Move::Play(r, c))),
+            None => return Err(format!("`{}` is not a list of squares", token)),
+        }
+    }
+    Ok(moves)
+}
+
+// Writes moves the way `parse` reads them.
+pub fn format(moves: &[Move], notation: Notation) -> String {
+    let moves = moves.iter().filter_map(|mv| match mv {
+        Move::Play(row, col) => Some(notation.format(*row, *col)),
+        Move::Pass => Some("pass".to_string()),
+        _ => None,
+    });
+    moves.collect::<Vec<_>>().join(" ")
+}
+
+// Plays `moves` out from the starting position, making sure every one
+// of them is legal. Returns every position along the way, starting with
+// the first.
+pub fn replay(moves: &[Move]) -> Result<Vec<Position>, String> {
+    replay_from(setup::start(), moves)
+}
+
+// The same, from a position other than this run's start.
+pub fn replay_from(start: &Setup, moves: &[Move]) -> Result<Vec<Position>, String> {
+    let mut positions = vec![Position {
+        board: start.board.clone(),
+        turn: start.turn,
+        played: None,
+        mv: None,
+    }];
+    for (i, &played) in moves.iter().enumerate() {
+        let last = positions.last().expect("starting position");
+        if last.is_over() {
+            return Err(format!("move {}: the game is already over", i + 1));
+        }
+
+        let mut board = last.board.clone();
+        let turn = last.turn;
+        match played {
+            Move::Play(row, col) => {
+                if let Err(e) = Move::check_move(&mut board, turn, row, col) {
+                    return Err(format!("move {}: {}: {}", i + 1, played, e));
+                }
+            }
+            // Transcripts often write out forced passes, which were
+            // already made when the other player had to skip.
+            Move::Pass if matches!(last.mv, Some(Move::Skip)) => continue,
+            Move::Pass if !Move::check_playablity(&board, turn) => {
+                return Err(format!("move {}: cannot pass", i + 1));
+            }
+            Move::Pass => {}
+            _ => return Err(format!("move {}: {} is not a move", i + 1, played)),
+        }
+        let (turn, mv) = Move::apply_move(&mut board, turn, played);
+        board.record_changes(&last.board);
+        positions.push(Position {
+            board,
+            turn,
+            played: Some(played),
+            mv,
+        });
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn squares(moves: &[Move]) -> Vec<(usize, usize)> {
+        moves
+            .iter()
+            .filter_map(|mv| match mv {
+                Move::Play(row, col) => Some((*row, *col)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_squares_written_together_or_apart() {
+        let together = parse("f5d6C3", Notation::Standard).unwrap();
+        let apart = parse("1. f5 d6 2. c3", Notation::Standard).unwrap();
+        assert_eq!(squares(&together), vec![(5, 6), (6, 4), (3, 3)]);
+        assert_eq!(squares(&apart), squares(&together));
+        assert_eq!(
+            squares(&parse("6e", Notation::Classic).unwrap()),
+            vec![(5, 6)]
+        );
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_square() {
+        assert!(parse("f5d", Notation::Standard).is_err());
+        assert!(parse("i9", Notation::Standard).is_err());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let moves = parse("f5 pass d6", Notation::Standard).unwrap();
+        assert_eq!(format(&moves, Notation::Standard), "f5 pass d6");
+        assert_eq!(format(&moves, Notation::Classic), "6e pass 4f");
+    }
+
+    #[test]
+    fn replays_legal_moves_only() {
+        let moves = parse("f5 d6 c3", Notation::Standard).unwrap();
+        let positions = replay(&moves).unwrap();
+        assert_eq!(positions.len(), 4);
+        assert!(positions[3].turn == Turn::White);
+        assert_eq!(positions[3].board.count_discs(), (5, 2));
+
+        let occupied = parse("f5 f5", Notation::Standard).unwrap();
+        let err = replay(&occupied).err().unwrap();
+        assert!(err.ends_with("square occupied"), "{}", err);
+        let nowhere = parse("a1", Notation::Standard).unwrap();
+        let err = replay(&nowhere).err().unwrap();
+        assert!(err.ends_with("no discs flipped"), "{}", err);
+    }
+
+    #[test]
+    fn replays_a_chosen_pass() {
+        let moves = parse("f5 pass", Notation::Standard).unwrap();
+        let positions = replay(&moves).unwrap();
+        assert!(positions[2].turn == Turn::Black);
+    }
+}
@@ -0,0 +1,562 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::events::{self, Move, Notation};
+use crate::review;
+use crate::setup::{self, Setup};
+use crate::theme;
+use crate::transcript::{self, Position};
+
+// A game with the variations tried along the way, as a tree of moves.
+// Every move can carry a comment and an evaluation.
+//
+// Trees are saved as the moves of the main line, with each variation in
+// brackets after the move it stands in for, comments in braces and
+// evaluations in square brackets, like
+//
+//     f5 d6 {The usual.} c3 [+2] (c5 f4 (f6 {Rare.})) d3
+//
+// Here c3 and c5 are both answers to d6, and f4 and f6 to c5. A position
+// line on top gives a start of its own, as in saved games. A tree with
+// no variations, comments or evaluations is a plain transcript.
+
+// One move, and the moves tried after it. The first of those is the main
+// line and the rest are its variations.
+pub struct Node {
+    // The root, before the first move, has none.
+    pub mv: Option<Move>,
+    pub comment: String,
+    // In discs from Black's side, like the evaluations of a review.
+    pub eval: Option<i32>,
+    pub children: Vec<Node>,
+}
+
+pub struct Tree {
+    pub start: Setup,
+    pub root: Node,
+}
+
+// Where a tree is read up to: the moves as written, and the brackets.
+enum Token {
+    Open,
+    Close,
+    Comment(String),
+    Eval(i32),
+    Word(String),
+}
+
+impl Node {
+    fn new(mv: Option<Move>) -> Self {
+        Node {
+            mv,
+            comment: String::new(),
+            eval: None,
+            children: Vec::new(),
+        }
+    }
+
+    // The move with what is written about it, as `format` writes it.
+    fn write(&self, notation: Notation) -> String {
+        let mut text = match self.mv {
+            Some(Move::Play(row, col)) => notation.format(row, col),
+            _ => "pass".to_string(),
+        };
+        if let Some(eval) = self.eval {
+            text.push_str(&format!(" [{:+}]", eval));
+        }
+        if !self.comment.is_empty() {
+            text.push_str(&format!(" {{{}}}", self.comment));
+        }
+        text
+    }
+}
+
+impl Tree {
+    pub fn new(start: Setup) -> Self {
+        Tree {
+            start,
+            root: Node::new(None),
+        }
+    }
+
+    // The node a path of child indices leads to from the root.
+    pub fn node(&self, path: &[usize]) -> &Node {
+        path.iter().fold(&self.root, |node, &i| &node.children[i])
+    }
+
+    pub fn node_mut(&mut self, path: &[usize]) -> &mut Node {
+        path.iter()
+            .fold(&mut self.root, |node, &i| &mut node.children[i])
+    }
+
+    // The moves from the start to the end of `path`.
+    pub fn moves(&self, path: &[usize]) -> Vec<Move> {
+        let mut node = &self.root;
+        let mut moves = Vec::new();
+        for &i in path {
+            node = &node.children[i];
+            moves.extend(node.mv);
+        }
+        moves
+    }
+
+    // Every position from the start to the end of `path`.
+    pub fn positions(&self, path: &[usize]) -> Vec<Position> {
+        transcript::replay_from(&self.start, &self.moves(path))
+            .expect("moves are checked as they are added")
+    }
+
+    // Plays `mv` after the end of `path`: into the variation that starts
+    // with it if there is one, or else a new one after the others. Returns
+    // which it was, or `None` for a pass the tree makes by itself.
+    pub fn play(&mut self, path: &[usize], mv: Move) -> Result<Option<usize>, String> {
+        let mut moves = self.moves(path);
+        moves.push(mv);
+        let positions = transcript::replay_from(&self.start, &moves)?;
+        if positions.len() < moves.len() + 1 {
+            return Ok(None);
+        }
+        let node = self.node_mut(path);
+        if let Some(i) = node.children.iter().position(|child| child.mv == Some(mv)) {
+            return Ok(Some(i));
+        }
+        node.children.push(Node::new(Some(mv)));
+        Ok(Some(node.children.len() - 1))
+    }
+
+    // Moves to the variation `step` places along from the one at the end
+    // of `path`, going round. False if there is no other.
+    pub fn switch(&self, path: &mut [usize], step: isize) -> bool {
+        let (last, above) = match path.split_last_mut() {
+            Some(split) => split,
+            None => return false,
+        };
+        let count = self.node(above).children.len() as isize;
+        if count < 2 {
+            return false;
+        }
+        *last = (*last as isize + step).rem_euclid(count) as usize;
+        true
+    }
+
+    // Moves the variation `path` is in one place up, where it branches
+    // off closest to the end of `path`. Promoting the first variation
+    // makes it the main line. False if `path` is on the main line already.
+    pub fn promote(&mut self, path: &mut [usize]) -> bool {
+        let depth = match path.iter().rposition(|&i| i > 0) {
+            Some(depth) => depth,
+            None => return false,
+        };
+        let i = path[depth];
+        self.node_mut(&path[..depth]).children.swap(i - 1, i);
+        path[depth] = i - 1;
+        true
+    }
+
+    // Takes the move at the end of `path` out, with everything after it,
+    // and goes back to the move before. False at the start.
+    pub fn delete(&mut self, path: &mut Vec<usize>) -> bool {
+        match path.pop() {
+            Some(i) => {
+                self.node_mut(path).children.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // What there is to know about where `path` leads: the line it is on,
+    // which variation, what is written about the move, and where the tree
+    // goes from there.
+    pub fn describe(&self, path: &[usize]) -> Vec<String> {
+        let to_move = theme::glyphs().to_move;
+        let mut line: Vec<String> = self.moves(path).iter().map(Move::to_string).collect();
+        if let Some(last) = line.last_mut() {
+            *last = format!("{}{}", to_move, last);
+        } else {
+            line.push(to_move.to_string());
+        }
+        let mut node = self.node(path);
+        while let Some(main) = node.children.first() {
+            line.push(main.mv.map_or(String::new(), |mv| mv.to_string()));
+            node = main;
+        }
+        let mut lines = vec![format!("Line: {}", line.join(" "))];
+
+        let node = self.node(path);
+        let mut here = match path.split_last() {
+            Some((&i, above)) => {
+                let count = self.node(above).children.len();
+                match i {
+                    _ if count == 1 => format!("Move {}", path.len()),
+                    0 => format!("Move {}, main line, 1 of {}", path.len(), count),
+                    _ => format!("Move {}, variation, {} of {}", path.len(), i + 1, count),
+                }
+            }
+            None => "Start".to_string(),
+        };
+        if let Some(eval) = node.eval {
+            here.push_str(&format!(", evaluated {:+}", eval));
+        }
+        lines.push(here);
+        if !node.comment.is_empty() {
+            lines.push(format!("{{{}}}", node.comment));
+        }
+        if node.children.len() > 1 {
+            let next: Vec<String> = node
+                .children
+                .iter()
+                .filter_map(|child| child.mv.map(|mv| mv.to_string()))
+                .collect();
+            lines.push(format!("Next: {}", next.join(", ")));
+        }
+        lines
+    }
+
+    // Reads a tree written by `format`, or any transcript, from `start`
+    // unless it has a position line of its own.
+    pub fn parse(text: &str, notation: Notation, start: Setup) -> Result<Self, String> {
+        let (own, text) = setup::split(text)?;
+        let mut tree = Tree::new(own.unwrap_or(start));
+        let mut tokens = tokens(text)?.into_iter();
+        tree.read_line(&mut tokens, &[], notation, false)?;
+        Ok(tree)
+    }
+
+    // Reads moves into the tree after the end of `path`, each after the
+    // one before, until the end of a variation or of the text.
+    fn read_line(
+        &mut self,
+        tokens: &mut impl Iterator<Item = Token>,
+        path: &[usize],
+        notation: Notation,
+        nested: bool,
+    ) -> Result<(), String> {
+        let size = self.start.board.size;
+        let mut line = path.to_vec();
+        loop {
+            match tokens.next() {
+                None if nested => return Err("a variation is missing its `)`".to_string()),
+                None => return Ok(()),
+                Some(Token::Close) if nested => return Ok(()),
+                Some(Token::Close) => return Err("a `)` without its `(`".to_string()),
+                Some(Token::Open) if line.len() == path.len() => {
+                    return Err("a variation has to follow the move it stands in for".to_string())
+                }
+                Some(Token::Open) => {
+                    let above = &line[..line.len() - 1];
+                    self.read_line(tokens, above, notation, true)?;
+                }
+                Some(Token::Comment(comment)) => self.node_mut(&line).comment = comment,
+                Some(Token::Eval(eval)) => self.node_mut(&line).eval = Some(eval),
+                Some(Token::Word(word)) => {
+                    let numbered =
+                        word.ends_with('.') && word.trim_end_matches('.').parse::<u32>().is_ok();
+                    if numbered {
+                        continue;
+                    }
+                    let moves = match word.to_ascii_lowercase().as_str() {
+                        "pass" | "ps" | "--" | "skip" => vec![Move::Pass],
+                        _ => match notation.parse(&word, size) {
+                            Some(squares) => {
+                                squares.into_iter().map(|(r, c)| Move::Play(r, c)).collect()
+                            }
+                            None => return Err(format!("`{}` is not a list of squares", word)),
+                        },
+                    };
+                    for mv in moves {
+                        // A forced pass written out was made already.
+                        if let Some(i) = self.play(&line, mv)? {
+                            line.push(i);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Writes the tree the way `parse` reads it.
+    pub fn format(&self, notation: Notation) -> String {
+        let mut words = Vec::new();
+        if !self.start.is_standard() {
+            words.push(format!("{}\n", self.start));
+        }
+        if let Some(eval) = self.root.eval {
+            words.push(format!("[{:+}]", eval));
+        }
+        if !self.root.comment.is_empty() {
+            words.push(format!("{{{}}}", self.root.comment));
+        }
+        write_line(&self.root, &mut words, notation);
+        words.join(" ").replace("\n ", "\n")
+    }
+}
+
+// `othello edit`: walks a tree of moves, adding to it, and saves it to
+// `file` when asked. A file that isn't there yet starts a new tree.
+pub fn run(file: &Path) -> Result<(), String> {
+    let config = config::current();
+    let notation = config.notation;
+    let start = Setup::new(&config.start, &config.holes, config.size)?;
+    let mut tree = match fs::read_to_string(file) {
+        Ok(text) => Tree::parse(&text, notation, start)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Tree::new(start),
+        Err(e) => return Err(format!("failed to read {}: {}", file.display(), e)),
+    };
+    tree.start.fits(config.size)?;
+    setup::init(tree.start.clone());
+    let theme = theme::current();
+    let mut path = Vec::new();
+    let mut saved = true;
+    let mut quitting = false;
+    let mut notice: Option<String> = None;
+    loop {
+        let positions = tree.positions(&path);
+        let position = positions.last().expect("starting position");
+        position.board.draw(position.turn, position.mv, None);
+        for line in tree.describe(&path) {
+            println!("{}", line);
+        }
+        if let Some(notice) = notice.take() {
+            println!("{}", notice);
+        }
+        println!("Enter moves to play or follow them, or nothing to go on along the line.");
+        println!("`b` goes back, `n` and `p` to the next and previous variation, `s` to the start");
+        println!("and `e` to the end of the line. `promote` moves this variation up, and `delete`");
+        println!("takes out this move and all after it. `c <text>` comments on the move, and");
+        println!(
+            "`v <discs>` gives it an evaluation for Black (`v` asks the engine, `v -` clears)."
+        );
+        println!("`w [<file>]` saves, and `q` quits.");
+
+        let line = match events::read_line(None).map_err(|e| e.to_string())? {
+            Some(line) => line,
+            None => continue,
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let error = |text: &str| Some(theme.error.paint(text).to_string());
+        let was_quitting = quitting;
+        quitting = false;
+        match command {
+            "" if tree.node(&path).children.is_empty() => {
+                notice = error("This is the end of the line.")
+            }
+            "" => path.push(0),
+            "b" if path.pop().is_none() => notice = error("This is the start."),
+            "b" => {}
+            "n" | "p" => {
+                let step = if command == "n" { 1 } else { -1 };
+                if !tree.switch(&mut path, step) {
+                    notice = error("There is no other variation here.");
+                }
+            }
+            "s" => path.clear(),
+            "e" => {
+                while !tree.node(&path).children.is_empty() {
+                    path.push(0);
+                }
+            }
+            "promote" if tree.promote(&mut path) => saved = false,
+            "promote" => notice = error("This is the main line already."),
+            "delete" if tree.delete(&mut path) => saved = false,
+            "delete" => notice = error("There is no move to delete at the start."),
+            "c" => {
+                tree.node_mut(&path).comment = rest.replace(['{', '}'], "");
+                saved = false;
+            }
+            "v" => {
+                let eval = match rest {
+                    "" => {
+                        println!("Evaluating...");
+                        Some(review::evaluate(position, review::DEFAULT_LEVEL))
+                    }
+                    "-" => None,
+                    _ => match rest.parse() {
+                        Ok(eval) => Some(eval),
+                        Err(_) => {
+                            notice = error(&format!("`{}` is not a number of discs.", rest));
+                            continue;
+                        }
+                    },
+                };
+                tree.node_mut(&path).eval = eval;
+                saved = false;
+            }
+            "w" => {
+                let target = if rest.is_empty() {
+                    file.to_path_buf()
+                } else {
+                    PathBuf::from(rest)
+                };
+                match fs::write(&target, tree.format(notation) + "\n") {
+                    Ok(()) => {
+                        saved = true;
+                        let text = format!("Saved to {}.", target.display());
+                        notice = Some(theme.info.paint(&text).to_string());
+                    }
+                    Err(e) => {
+                        notice = error(&format!("Failed to save to {}: {}", target.display(), e))
+                    }
+                }
+            }
+            "q" if saved || was_quitting => return Ok(()),
+            "q" => {
+                quitting = true;
+                notice = error("The tree has changed since it was saved. Enter `w` to save it, or `q` again to quit.");
+            }
+            _ => match transcript::parse(line, notation) {
+                Ok(moves) => {
+                    for mv in moves {
+                        let count = tree.node(&path).children.len();
+                        match tree.play(&path, mv) {
+                            Ok(Some(i)) => {
+                                saved &= tree.node(&path).children.len() == count;
+                                path.push(i);
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                // Without the move number, which is where the cursor is.
+                                let reason =
+                                    e.split_once(": ").map_or(e.as_str(), |(_, reason)| reason);
+                                notice = error(&format!("Not a legal move: {}.", reason));
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(_) => notice = error(&format!("`{}` is not a move or a command.", line)),
+            },
+        }
+    }
+}
+
+// Writes the moves after `node`: its main line, with every variation in
+// brackets after the move it stands in for.
+fn write_line(node: &Node, words: &mut Vec<String>, notation: Notation) {
+    let mut node = node;
+    while let Some((main, others)) = node.children.split_first() {
+        words.push(main.write(notation));
+        for other in others {
+            let mut variation = vec![other.write(notation)];
+            write_line(other, &mut variation, notation);
+            words.push(format!("({})", variation.join(" ")));
+        }
+        node = main;
+    }
+}
+
+fn tokens(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '{' => {
+                let comment = closed(&mut chars, '}').ok_or("a comment is missing its `}`")?;
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '[' => {
+                let eval = closed(&mut chars, ']').ok_or("an evaluation is missing its `]`")?;
+                let eval = eval
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("`[{}]` is not an evaluation", eval))?;
+                tokens.push(Token::Eval(eval));
+            }
+            c if c.is_whitespace() || c == ',' => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "(){}[],".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Everything up to `end`, which is skipped. `None` if it never comes.
+fn closed(chars: &mut impl Iterator<Item = char>, end: char) -> Option<String> {
+    let mut text = String::new();
+    for c in chars {
+        if c == end {
+            return Some(text);
+        }
+        text.push(c);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(text: &str) -> Tree {
+        Tree::parse(text, Notation::Standard, Setup::standard(8)).unwrap()
+    }
+
+    #[test]
+    fn reads_and_writes_variations_comments_and_evaluations() {
+        let text = "[+0] {Even.} f5 d6 {The usual.} c3 [+2] (c5 f4 (f6 {Rare.})) d3";
+        let tree = tree(text);
+        assert_eq!(tree.format(Notation::Standard), text);
+        assert_eq!(tree.root.eval, Some(0));
+        assert_eq!(tree.root.comment, "Even.");
+        let d6 = tree.node(&[0, 0]);
+        assert_eq!(d6.comment, "The usual.");
+        assert_eq!(d6.children.len(), 2);
+        assert_eq!(d6.children[0].eval, Some(2));
+        assert_eq!(tree.node(&[0, 0, 1]).children.len(), 2);
+        assert_eq!(
+            transcript::format(&tree.moves(&[0, 0, 1, 1]), Notation::Standard),
+            "f5 d6 c5 f6"
+        );
+
+        // Plain transcripts are trees with a single line.
+        let plain = Tree::parse("1. f5 d6 2. c3", Notation::Standard, Setup::standard(8)).unwrap();
+        assert_eq!(plain.format(Notation::Standard), "f5 d6 c3");
+        let err = |text| Tree::parse(text, Notation::Standard, Setup::standard(8)).err();
+        assert!(err("f5 (d6)").unwrap().contains("move 1"));
+        assert!(err("f5 d6 (f6").unwrap().contains("`)`"));
+        assert!(err("f5 {open").is_some());
+        assert!(err("(f5)").is_some());
+    }
+
+    #[test]
+    fn edits_the_tree() {
+        let mut tree = tree("f5 d6 c3 (c5 f4) (c4)");
+        let mut path = vec![0, 0, 1, 0];
+        assert!(tree.switch(&mut path[..3], 1));
+        assert_eq!(path[..3], [0, 0, 2]);
+        assert!(tree.switch(&mut path[..3], 1));
+        assert_eq!(path[2], 0);
+        assert!(!tree.switch(&mut path[..1], 1));
+
+        path = vec![0, 0, 2];
+        assert!(tree.promote(&mut path));
+        assert_eq!(path, [0, 0, 1]);
+        assert!(tree.promote(&mut path));
+        assert_eq!(tree.format(Notation::Standard), "f5 d6 c4 (c3) (c5 f4)");
+        assert!(!tree.promote(&mut path));
+
+        // Playing a move that is there follows it.
+        assert_eq!(tree.play(&[0, 0], Move::Play(5, 3)), Ok(Some(2)));
+        assert_eq!(tree.play(&[0, 0], Move::Play(6, 3)), Ok(Some(3)));
+        assert!(tree.play(&[0, 0], Move::Play(1, 1)).is_err());
+
+        let mut path = vec![0, 0, 2];
+        assert!(tree.delete(&mut path));
+        assert_eq!(path, [0, 0]);
+        assert_eq!(tree.format(Notation::Standard), "f5 d6 c4 (c3) (c6)");
+    }
+}
@@ -0,0 +1,427 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEventKind,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+
+use crate::archive;
+use crate::board::{self, Board};
+use crate::clock::{Clock, TimeControl};
+use crate::config;
+use crate::engine;
+use crate::events::{InputHandler, Move, PlayabilityChecker, Turn};
+use crate::game;
+use crate::profiles;
+use crate::review;
+use crate::setup;
+use crate::theme;
+use crate::transcript;
+
+// How far the side panel sits from the board.
+const PANEL_GAP: u16 = 3;
+// How many lines of recent moves the side panel has room for.
+const MOVE_LIST: usize = 6;
+
+// Puts the terminal into raw mode on the alternate screen, and puts it
+// back the way it was when dropped, even if the game panics.
+pub struct Screen(pub Stdout);
+
+impl Screen {
+    pub fn new() -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, EnableMouseCapture, cursor::Hide)?;
+        Ok(Screen(out))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.0,
+            cursor::Show,
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Tui {
+    board: Board,
+    clock: Option<Clock>,
+    deadline: Option<Instant>,
+    // Whoever is to move, or once the game is `over`, whoever it ended on.
+    turn: Turn,
+    mv: Option<Move>,
+    over: bool,
+    cursor: (usize, usize),
+    history: Vec<Move>,
+    // Short-lived feedback, like a pass that isn't allowed.
+    notice: Option<String>,
+    // How the ratings moved, once the game is over.
+    ratings: Option<String>,
+}
+
+// Plays a game, picking up after `moves` if there are any.
+// They must already have been checked with `transcript::replay`.
+pub fn start(time_control: Option<TimeControl>, moves: &[Move]) -> io::Result<()> {
+    let positions = transcript::replay(moves).expect("checked moves");
+    let mut history = Vec::new();
+    for position in &positions {
+        history.extend(position.played);
+        if let Some(Move::Skip) = position.mv {
+            history.push(Move::Skip);
+        }
+    }
+    let position = positions.into_iter().last().expect("starting position");
+
+    let size = position.board.size;
+    let mut screen = Screen::new()?;
+    let mut tui = Tui {
+        board: position.board,
+        clock: time_control.map(Clock::new),
+        deadline: None,
+        turn: position.turn,
+        mv: position.mv,
+        over: false,
+        cursor: (size / 2, size / 2),
+        history,
+        notice: None,
+        ratings: None,
+    };
+    let turn = tui.turn;
+    tui.deadline = tui.clock.as_mut().map(|c| c.start(turn));
+    // Whether `q` left a game still in progress, to be saved.
+    let mut saving = false;
+
+    loop {
+        if tui.deadline.is_some_and(|d| Instant::now() >= d) {
+            tui.finish(Move::Timeout);
+        }
+        tui.draw(&mut screen.0)?;
+
+        if !tui.over {
+            if let Some(level) = config::current().player(tui.turn).ai {
+                match engine::pick(&tui.board, tui.turn, level, tui.clock.as_ref()) {
+                    Move::Play(row, col) => tui.play((row, col)),
+                    _ => tui.pass(),
+                }
+                continue;
+            }
+        }
+        // Poll rather than block, so the clocks keep ticking on screen.
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                match key.code {
+                    _ if ctrl_c => break,
+                    KeyCode::Esc => break,
+                    KeyCode::Char('q') => {
+                        saving = !tui.over;
+                        break;
+                    }
+                    _ if tui.over => {}
+                    KeyCode::Up | KeyCode::Char('w') => tui.step(-1, 0),
+                    KeyCode::Down | KeyCode::Char('s') => tui.step(1, 0),
+                    KeyCode::Left | KeyCode::Char('a') => tui.step(0, -1),
+                    KeyCode::Right | KeyCode::Char('d') => tui.step(0, 1),
+                    KeyCode::Enter | KeyCode::Char(' ') => tui.play(tui.cursor),
+                    KeyCode::Char('p') => tui.pass(),
+                    KeyCode::Char('r') => tui.finish(Move::Resign),
+                    KeyCode::Char('h') => tui.board.hints = !tui.board.hints,
+                    _ => {}
+                }
+            }
+            Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                match square_at(&tui.board, mouse.row, mouse.column) {
+                    Some(square) if !tui.over => {
+                        tui.cursor = square;
+                        tui.play(square);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Back on the normal screen for anything printed afterwards.
+    drop(screen);
+    if saving && !tui.history.is_empty() {
+        game::save_and_quit(&tui.history);
+    }
+    if let Some(Move::Win(_, _)) | Some(Move::Resign) = tui.mv {
+        review::offer(&tui.history);
+    }
+    Ok(())
+}
+
+// Where the cursor goes from `cursor` on a board `size` squares across,
+// wrapping around the edges.
+fn step(cursor: (usize, usize), size: usize, rows: isize, cols: isize) -> (usize, usize) {
+    let wrap = |i: usize, d: isize| (i as isize - 1 + d).rem_euclid(size as isize) as usize + 1;
+    (wrap(cursor.0, rows), wrap(cursor.1, cols))
+}
+
+// The square under a click at `row` and `column` of the screen, if the
+// click was on the board and not on its labels.
+fn square_at(board: &Board, row: u16, column: u16) -> Option<(usize, usize)> {
+    let (row, col) = (row as usize, column as usize / board.cell_width());
+    let on_board = |i| (1..=board.size).contains(&i);
+    (on_board(row) && on_board(col)).then_some((row, col))
+}
+
+// The moves in `history`, one line per full move. Skips are recorded too,
+// so every pair of entries is one full move, black then white, once a
+// game where White moved first is padded out.
+fn move_lines(history: &[Move], white_first: bool) -> Vec<String> {
+    let mut history = history.iter().map(Move::to_string).collect::<Vec<_>>();
+    if white_first {
+        history.insert(0, "...".to_string());
+    }
+    let moves = history.chunks(2).enumerate().map(|(i, pair)| match pair {
+        [black, white] => format!("{:>3}. {:<6} {}", i + 1, black, white),
+        [black] => format!("{:>3}. {}", i + 1, black),
+        _ => unreachable!(),
+    });
+    moves.collect()
+}
+
+impl Tui {
+    // Moves the cursor, wrapping around the edges of the board.
+    fn step(&mut self, rows: isize, cols: isize) {
+        self.cursor = step(self.cursor, self.board.size, rows, cols);
+    }
+
+    fn play(&mut self, (row, col): (usize, usize)) {
+        let before = self.board.clone();
+        match Move::check_move(&mut self.board, self.turn, row, col) {
+            Ok(()) => {
+                let res = Move::apply_move(&mut self.board, self.turn, Move::Play(row, col));
+                self.board.record_changes(&before);
+                self.advance(Move::Play(row, col), res);
+            }
+            Err(e) => self.notice = Some(format!("Invalid move: {}.", e)),
+        }
+    }
+
+    fn pass(&mut self) {
+        // Passing is allowed as long as the other player has a move to make.
+        if Move::check_playablity(&self.board, self.turn) {
+            let res = Move::apply_move(&mut self.board, self.turn, Move::Pass);
+            self.advance(Move::Pass, res);
+        } else {
+            self.notice = Some("Cannot pass.".to_string());
+        }
+    }
+
+    // Ends the game on the current player, by resigning or running out of time.
+    fn finish(&mut self, mv: Move) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop(self.turn, false);
+        }
+        self.history.push(mv);
+        self.mv = Some(mv);
+        self.deadline = None;
+        self.over = true;
+        self.record(mv);
+    }
+
+    // Hands the turn over after a completed move, and restarts the clock.
+    fn advance(&mut self, played: Move, (turn, mv): (Turn, Option<Move>)) {
+        if let Some(clock) = self.clock.as_mut() {
+            if !clock.stop(self.turn, true) {
+                return self.finish(Move::Timeout);
+            }
+        }
+        self.history.push(played);
+        if let Some(Move::Skip) = mv {
+            self.history.push(Move::Skip);
+        }
+        self.notice = None;
+        self.turn = turn;
+        self.mv = mv;
+        if let Some(end @ Move::Win(_, _)) = mv {
+            self.deadline = None;
+            self.over = true;
+            self.record(end);
+        } else {
+            self.deadline = self.clock.as_mut().map(|c| c.start(turn));
+        }
+    }
+
+    // Counts the game that just ended towards both players' profiles and
+    // archives it, and says how the ratings moved.
+    fn record(&mut self, end: Move) {
+        let outcome = match profiles::outcome(&self.board, self.turn, end) {
+            Some(outcome) => outcome,
+            None => return,
+        };
+        let config = config::current();
+        let (black, white) = (profiles::key(&config.black), profiles::key(&config.white));
+        match profiles::record_game(&black, &white, &outcome) {
+            Ok(ratings) => self.ratings = ratings,
+            Err(e) => self.notice = Some(format!("Failed to record the game: {}", e)),
+        }
+        if let Err(e) = archive::store(&black, &white, &outcome, &self.history) {
+            self.notice = Some(format!("Failed to archive the game: {}", e));
+        }
+    }
+
+    fn draw(&self, out: &mut Stdout) -> io::Result<()> {
+        let legal = self.board.hints_for(self.turn, self.mv);
+        let panel = self.panel();
+        // Every cell is `cell_width` columns wide, labels included.
+        let width = self.board.size + 1;
+        // Small boards are shorter than the panel beside them.
+        let height = width.max(panel.len());
+        for row in 0..height {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            let cols = if row < width { width } else { 0 };
+            for col in 0..cols {
+                let cell = if !self.over && self.cursor == (row, col) {
+                    self.board
+                        .pad(self.board.cells[row][col].highlighted(theme::current().cursor))
+                } else {
+                    self.board.render(row, col, &legal)
+                };
+                queue!(out, Print(cell))?;
+            }
+            queue!(
+                out,
+                cursor::MoveTo(
+                    (width * self.board.cell_width()) as u16 + PANEL_GAP,
+                    row as u16
+                )
+            )?;
+            if let Some(line) = panel.get(row) {
+                queue!(out, Print(line))?;
+            }
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+        }
+
+        let mut below = vec![String::new(), self.board.status()];
+        if let Some(message) = board::message(self.turn, self.mv) {
+            below.extend(message.to_string().lines().map(String::from));
+        }
+        if let Some(notice) = &self.notice {
+            below.push(theme::current().error.paint(notice).to_string());
+        }
+        below.extend(self.ratings.clone());
+        below.push(String::new());
+        below.push(if self.over {
+            "q quit".to_string()
+        } else {
+            "arrows/WASD move  enter/click place  p pass  r resign  h hints  q save and quit"
+                .to_string()
+        });
+        for (i, line) in below.iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(0, (height + i) as u16),
+                Print(line),
+                Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+
+    // Scores and clocks, then the most recent moves, one line per full move.
+    fn panel(&self) -> Vec<String> {
+        let (black, white) = self.board.count_discs();
+        let glyphs = theme::glyphs();
+        let side = |turn: Turn, count: u16| {
+            let to_move = if !self.over && self.turn == turn {
+                glyphs.to_move
+            } else {
+                " "
+            };
+            let disc = glyphs.mark(turn);
+            let name = config::current().name(turn);
+            let mut line = format!("{} {} {:<6}{:>3}", to_move, disc, name, count);
+            if let Some(clock) = &self.clock {
+                line.push_str(&format!("  {}", clock.show(turn)));
+            }
+            line
+        };
+        let mut lines = vec![
+            side(Turn::Black, black),
+            side(Turn::White, white),
+            String::new(),
+        ];
+
+        let moves = move_lines(&self.history, setup::start().turn == Turn::White);
+        let skip = moves.len().saturating_sub(MOVE_LIST);
+        lines.extend(moves.into_iter().skip(skip));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_the_cursor_around_the_edges() {
+        assert_eq!(step((1, 1), 8, -1, 0), (8, 1));
+        assert_eq!(step((1, 8), 8, 0, 1), (1, 1));
+        assert_eq!(step((8, 8), 8, 1, 1), (1, 1));
+        assert_eq!(step((4, 5), 8, 1, -1), (5, 4));
+    }
+
+    #[test]
+    fn finds_the_square_under_a_click() {
+        let board = Board::with_size(8);
+        assert_eq!(square_at(&board, 1, 2), Some((1, 1)));
+        assert_eq!(square_at(&board, 4, 9), Some((4, 4)));
+        assert_eq!(square_at(&board, 0, 4), None);
+        assert_eq!(square_at(&board, 3, 1), None);
+        assert_eq!(square_at(&board, 9, 4), None);
+
+        // Cells are three columns wide from 10x10 up.
+        let board = Board::with_size(12);
+        assert_eq!(square_at(&board, 12, 36), Some((12, 12)));
+        assert_eq!(square_at(&board, 5, 15), Some((5, 5)));
+        assert_eq!(square_at(&board, 5, 17), Some((5, 5)));
+        assert_eq!(square_at(&board, 5, 18), Some((5, 6)));
+        assert_eq!(square_at(&board, 5, 39), None);
+    }
+
+    #[test]
+    fn pairs_moves_up_after_skips_and_white_starts() {
+        let notation = config::current().notation;
+        let (a, b, c) = ((5, 6), (4, 6), (3, 5));
+        let square = |(row, col)| notation.format(row, col);
+        let history = [
+            Move::Play(a.0, a.1),
+            Move::Skip,
+            Move::Play(b.0, b.1),
+            Move::Play(c.0, c.1),
+        ];
+        assert_eq!(
+            move_lines(&history, false),
+            [
+                format!("  1. {:<6} skip", square(a)),
+                format!("  2. {:<6} {}", square(b), square(c)),
+            ]
+        );
+        assert_eq!(
+            move_lines(&history[..3], true),
+            [
+                format!("  1. ...    {}", square(a)),
+                format!("  2. skip   {}", square(b)),
+            ]
+        );
+    }
+}
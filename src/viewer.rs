@@ -0,0 +1,300 @@
+use std::io::{self, Stdout, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{cursor, queue};
+
+use crate::board;
+use crate::config;
+use crate::events::{Move, Turn};
+use crate::theme;
+use crate::transcript::Position;
+use crate::tui::Screen;
+
+// How far the side panel sits from the board, as in the game itself.
+const PANEL_GAP: u16 = 3;
+// How many lines of moves the panel shows around the current one.
+const MOVE_LIST: usize = 8;
+// How far Page Up and Page Down go.
+const PAGE: usize = 10;
+
+// Steps through a recorded game a ply at a time, in the full-screen UI.
+struct Viewer<'a> {
+    positions: &'a [Position],
+    names: [String; 2],
+    ply: usize,
+    // A move number being typed in to jump to.
+    typed: String,
+    notice: Option<String>,
+}
+
+// Shows the game played out in `positions`, with `names` for black and
+// white. Returns the ply to carry on playing from, if the viewer asked to.
+pub fn view(positions: &[Position], names: [String; 2]) -> io::Result<Option<usize>> {
+    let mut screen = Screen::new()?;
+    let mut viewer = Viewer {
+        positions,
+        names,
+        ply: 0,
+        typed: String::new(),
+        notice: None,
+    };
+    let last = positions.len() - 1;
+    loop {
+        viewer.draw(&mut screen.0)?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        viewer.notice = None;
+        match key.code {
+            _ if ctrl_c => return Ok(None),
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+            KeyCode::Right | KeyCode::Char('d') | KeyCode::Char(' ') => {
+                viewer.ply = (viewer.ply + 1).min(last)
+            }
+            KeyCode::Left | KeyCode::Char('a') => viewer.ply = viewer.ply.saturating_sub(1),
+            KeyCode::PageDown => viewer.ply = (viewer.ply + PAGE).min(last),
+            KeyCode::PageUp => viewer.ply = viewer.ply.saturating_sub(PAGE),
+            KeyCode::Home => viewer.ply = 0,
+            KeyCode::End => viewer.ply = last,
+            KeyCode::Char(digit) if digit.is_ascii_digit() && viewer.typed.len() < 3 => {
+                viewer.typed.push(digit)
+            }
+            KeyCode::Backspace => {
+                viewer.typed.pop();
+            }
+            KeyCode::Enter if !viewer.typed.is_empty() => {
+                match viewer.typed.parse::<usize>() {
+                    Ok(ply) if ply <= last => viewer.ply = ply,
+                    _ => viewer.notice = Some(format!("The game is {} moves long.", last)),
+                }
+                viewer.typed.clear();
+            }
+            KeyCode::Char('b') if positions[viewer.ply].is_over() => {
+                viewer.notice = Some("The game is over here.".to_string())
+            }
+            KeyCode::Char('b') => return Ok(Some(viewer.ply)),
+            _ => {}
+        }
+    }
+}
+
+impl Viewer<'_> {
+    fn draw(&self, out: &mut Stdout) -> io::Result<()> {
+        let position = &self.positions[self.ply];
+        let board = &position.board;
+        let legal = board.hints_for(position.turn, position.mv);
+        let panel = self.panel();
+        let width = board.size + 1;
+        let height = width.max(panel.len());
+        for row in 0..height {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            if row < width {
+                for col in 0..width {
+                    queue!(out, Print(board.render(row, col, &legal)))?;
+                }
+            }
+            queue!(
+                out,
+                cursor::MoveTo((width * board.cell_width()) as u16 + PANEL_GAP, row as u16)
+            )?;
+            if let Some(line) = panel.get(row) {
+                queue!(out, Print(line))?;
+            }
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+        }
+
+        let mut below = vec![String::new(), board.status()];
+        if let Some(message) = board::message(position.turn, position.mv) {
+            below.extend(message.to_string().lines().map(String::from));
+        }
+        if let Some(notice) = &self.notice {
+            below.push(theme::current().error.paint(notice).to_string());
+        }
+        below.push(String::new());
+        below.push(if self.typed.is_empty() {
+            "left/right step  pgup/pgdn 10 moves  home/end  type a number + enter to go to it"
+                .to_string()
+        } else {
+            format!("Go to move {}_", self.typed)
+        });
+        below.push("b play on from here  q quit".to_string());
+        for (i, line) in below.iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(0, (height + i) as u16),
+                Print(line),
+                Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+
+    // Scores, where the game is up to, and the moves around it.
+    fn panel(&self) -> Vec<String> {
+        let position = &self.positions[self.ply];
+        let (black, white) = position.board.count_discs();
+        let glyphs = theme::glyphs();
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(6);
+        let side = |turn: Turn, name: &str, count: u16| {
+            let to_move = if !position.is_over() && position.turn == turn {
+                glyphs.to_move
+            } else {
+                " "
+            };
+            format!(
+                "{} {} {:<width$}{:>3}",
+                to_move,
+                glyphs.mark(turn),
+                name,
+                count,
+                width = width
+            )
+        };
+        let mut lines = vec![
+            side(Turn::Black, &self.names[0], black),
+            side(Turn::White, &self.names[1], white),
+            String::new(),
+            format!("Move {} of {}", self.ply, self.positions.len() - 1),
+        ];
+        lines.push(match position.played {
+            Some(played) => format!(
+                "{} {}, {} flipped",
+                glyphs.mark(self.mover(self.ply)),
+                played,
+                match played {
+                    Move::Play(_, _) => position.board.flipped.len(),
+                    _ => 0,
+                }
+            ),
+            None => "Start".to_string(),
+        });
+        lines.push(String::new());
+
+        // One line per move, the current one marked.
+        let first = self.ply.saturating_sub(MOVE_LIST / 2).max(1);
+        let last = (first + MOVE_LIST).min(self.positions.len());
+        for ply in first..last {
+            let played = self.positions[ply].played.expect("a move after the start");
+            let marker = if ply == self.ply { glyphs.to_move } else { " " };
+            lines.push(format!(
+                "{} {:>3}. {} {}",
+                marker,
+                ply,
+                glyphs.mark(self.mover(ply)),
+                played
+            ));
+        }
+        lines
+    }
+
+    // Who made the move that led to `ply`.
+    fn mover(&self, ply: usize) -> Turn {
+        self.positions[ply - 1].turn
+    }
+}
+
+// The moves that lead up to `ply`, to play on from there. Forced passes
+// aren't among them, as the game makes those itself.
+pub fn moves_to(positions: &[Position], ply: usize) -> Vec<Move> {
+    positions[1..=ply].iter().filter_map(|p| p.played).collect()
+}
+
+// The names to show: what the record says, or else the configured players.
+pub fn names(black: Option<String>, white: Option<String>) -> [String; 2] {
+    let config = config::current();
+    [
+        black.unwrap_or_else(|| config.name(Turn::Black).to_string()),
+        white.unwrap_or_else(|| config.name(Turn::White).to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Notation;
+    use crate::setup::Setup;
+    use crate::transcript;
+
+    // Black has no move after f8, so white plays twice running.
+    fn game(text: &str) -> Vec<Position> {
+        let moves = transcript::parse(text, Notation::Standard).unwrap();
+        transcript::replay_from(&Setup::standard(8), &moves).unwrap()
+    }
+
+    fn viewer(positions: &[Position], ply: usize) -> Viewer<'_> {
+        Viewer {
+            positions,
+            names: ["alice".to_string(), "bob".to_string()],
+            ply,
+            typed: String::new(),
+            notice: None,
+        }
+    }
+
+    #[test]
+    fn shows_the_moves_around_the_current_one() {
+        let positions = game("e6 f6 d3 e7 e8 d8 g6 f8 g5");
+        let glyphs = theme::glyphs();
+        let played = |ply: usize| positions[ply].played.unwrap().to_string();
+
+        let start = viewer(&positions, 0).panel();
+        assert_eq!(start[3], "Move 0 of 9");
+        assert_eq!(start[4], "Start");
+        assert_eq!(start.len(), 6 + MOVE_LIST);
+        assert_eq!(
+            start[6],
+            format!("    1. {} {}", glyphs.black_mark, played(1))
+        );
+
+        let end = viewer(&positions, 9).panel();
+        assert_eq!(end[3], "Move 9 of 9");
+        // g5 takes f6 back.
+        assert_eq!(
+            end[4],
+            format!("{} {}, 1 flipped", glyphs.white_mark, played(9))
+        );
+        // The window keeps half its moves before the current one.
+        assert_eq!(end.len(), 6 + 5);
+        assert_eq!(
+            end[6],
+            format!("    5. {} {}", glyphs.black_mark, played(5))
+        );
+        assert_eq!(
+            end[9],
+            format!("    8. {} {}", glyphs.white_mark, played(8))
+        );
+        assert_eq!(
+            end[10],
+            format!(
+                "{}   9. {} {}",
+                glyphs.to_move,
+                glyphs.white_mark,
+                played(9)
+            )
+        );
+    }
+
+    #[test]
+    fn plays_on_after_a_forced_pass() {
+        let positions = game("e6 f6 d3 e7 e8 d8 g6 f8 g5");
+        assert!(positions[8].mv == Some(Move::Skip));
+        let passed = game("e6 f6 d3 e7 e8 d8 g6 f8 pass g5");
+        assert_eq!(passed.len(), positions.len());
+
+        for ply in [8, 9] {
+            let moves = moves_to(&positions, ply);
+            assert_eq!(moves.len(), ply);
+            let branch = transcript::replay_from(&Setup::standard(8), &moves).unwrap();
+            let (here, there) = (&positions[ply], branch.last().unwrap());
+            assert!(here.board.cells == there.board.cells);
+            assert!(here.turn == there.turn);
+        }
+        assert!(moves_to(&positions, 0).is_empty());
+    }
+}
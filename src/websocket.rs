@@ -0,0 +1,342 @@
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+use crate::events::{Move, Turn};
+use crate::protocol::{self, ClientMsg, ServerMsg};
+use crate::server::{self, Event};
+
+// The messages of `src/protocol.rs`, as JSON text frames for browsers
+// and other WebSocket clients. Every message is an object with a `type`,
+// and squares are strings in standard notation (`"d3"`).
+//
+// Client to server:
+//
+//     {"type": "hello", "name": "alice"}          log in; add "token" to take your seat back
+//     {"type": "list"}
+//     {"type": "create", "side": "black"}         the same as "seek"; "side" is optional
+//     {"type": "join", "game": 1}
+//     {"type": "watch", "game": 1}
+//     {"type": "leave"}
+//     {"type": "say", "text": "good luck"}
+//     {"type": "moves"}
+//     {"type": "move", "square": "d3"}
+//     {"type": "pass"}
+//     {"type": "resign"}
+//
+// Server to client:
+//
+//     {"type": "welcome", "name": "alice", "token": "..."}
+//     {"type": "open", "game": 1, "name": "alice", "side": "black"}
+//     {"type": "playing", "game": 1, "black": "alice", "white": "bob"}
+//     {"type": "listed"}
+//     {"type": "closed", "game": 1}
+//     {"type": "game", "game": 1, "side": "black"}    or "white" or "spectator"
+//     {"type": "waiting"}
+//     {"type": "players", "black": "alice", "white": "bob"}
+//     {"type": "board", "position": "...", "rows": ["--------", ...], "turn": "black"}
+//                                                  rows top to bottom, with X for black,
+//                                                  O for white, - for empty and # for holes
+//     {"type": "played", "side": "black", "move": "d3"}    or "pass"
+//     {"type": "skip", "side": "white"}
+//     {"type": "turn", "side": "white"}
+//     {"type": "moves", "squares": ["c3", "e3", "c5"]}
+//     {"type": "clock", "black": 299000, "white": 300000}  milliseconds left
+//     {"type": "away", "side": "white", "seconds": 60}
+//     {"type": "back", "side": "white"}
+//     {"type": "over", "end": "finished", "winner": "black", "black": 40, "white": 24}
+//     {"type": "chat", "name": "bob", "text": "good luck"}
+//     {"type": "error", "message": "it is not your turn"}
+
+// How long to wait for a frame before sending on what the server said.
+const POLL: Duration = Duration::from_millis(20);
+
+// Accepts WebSocket connections in the background, with a thread talking
+// to each of them.
+pub fn accept(listener: TcpListener, tx: Sender<Event>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            thread::spawn(move || talk(stream, tx));
+        }
+    });
+}
+
+// Passes frames from one client on to the server, and the server's
+// messages back, until either side is done.
+fn talk(stream: TcpStream, tx: Sender<Event>) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    if socket.get_ref().set_read_timeout(Some(POLL)).is_err() {
+        return;
+    }
+    let id = server::next_conn();
+    let (out, outbox) = mpsc::channel();
+    if tx.send(Event::Connected(id, out)).is_err() {
+        return;
+    }
+    'talk: loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => match parse(&text) {
+                Ok(msg) => {
+                    if tx.send(Event::Msg(id, msg)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let error = to_json(&ServerMsg::Error(e)).to_string();
+                    if socket.send(Message::Text(error)).is_err() {
+                        break;
+                    }
+                }
+            },
+            Ok(Message::Close(_)) => break,
+            // Pings are answered on their own.
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+        loop {
+            match outbox.try_recv() {
+                Ok(msg) => {
+                    let text = to_json(&msg).to_string();
+                    if socket.send(Message::Text(text)).is_err() {
+                        break 'talk;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                // The server is done with this client.
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    break 'talk;
+                }
+            }
+        }
+    }
+    let _ = tx.send(Event::Closed(id));
+}
+
+// Reads a client's JSON message.
+pub fn parse(text: &str) -> Result<ClientMsg, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+    let kind = value["type"].as_str().ok_or("a message needs a `type`")?;
+    let text_of = |key: &str| {
+        value[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or(format!("`{}` needs a `{}` string", kind, key))
+    };
+    let game = || {
+        let game = value["game"].as_u64().and_then(|id| u32::try_from(id).ok());
+        game.ok_or(format!("`{}` needs a `game` number", kind))
+    };
+    let msg = match kind {
+        "hello" => ClientMsg::Hello {
+            name: text_of("name")?,
+            token: value["token"].as_str().map(str::to_string),
+        },
+        "list" => ClientMsg::List,
+        "create" | "seek" => match value["side"].as_str() {
+            Some(side) => ClientMsg::Seek(protocol::parse_side(side)?),
+            None => ClientMsg::Seek(Turn::Black),
+        },
+        "join" => ClientMsg::Join(game()?),
+        "watch" => ClientMsg::Watch(game()?),
+        "leave" => ClientMsg::Leave,
+        "say" => ClientMsg::Say(text_of("text")?),
+        "moves" => ClientMsg::Moves,
+        "move" => {
+            let (row, col) = protocol::parse_square(&text_of("square")?)?;
+            ClientMsg::Move(row, col)
+        }
+        "pass" => ClientMsg::Pass,
+        "resign" => ClientMsg::Resign,
+        _ => return Err(format!("unknown message type `{}`", kind)),
+    };
+    Ok(msg)
+}
+
+// Writes a server message as JSON.
+pub fn to_json(msg: &ServerMsg) -> Value {
+    let side = |side: &Turn| protocol::side_name(*side);
+    match msg {
+        ServerMsg::Welcome { name, token } => {
+            json!({"type": "welcome", "name": name, "token": token})
+        }
+        ServerMsg::Open { id, name, side: s } => {
+            json!({"type": "open", "game": id, "name": name, "side": side(s)})
+        }
+        ServerMsg::Playing { id, black, white } => {
+            json!({"type": "playing", "game": id, "black": black, "white": white})
+        }
+        ServerMsg::Listed => json!({"type": "listed"}),
+        ServerMsg::Closed(id) => json!({"type": "closed", "game": id}),
+        ServerMsg::Game { id, side: s } => {
+            let side = match s {
+                Turn::Neither => "spectator",
+                s => side(s),
+            };
+            json!({"type": "game", "game": id, "side": side})
+        }
+        ServerMsg::Waiting => json!({"type": "waiting"}),
+        ServerMsg::Players { black, white } => {
+            json!({"type": "players", "black": black, "white": white})
+        }
+        ServerMsg::Board(position) => {
+            let (cells, turn) = position.split_once(' ').unwrap_or((position, "X"));
+            let size = (cells.len() as f64).sqrt() as usize;
+            let rows = (0..size)
+                .map(|row| &cells[row * size..(row + 1) * size])
+                .collect::<Vec<_>>();
+            let turn = if turn == "O" { "white" } else { "black" };
+            json!({"type": "board", "position": position, "rows": rows, "turn": turn})
+        }
+        ServerMsg::Played { side: s, mv } => {
+            let mv = match mv {
+                Move::Play(row, col) => protocol::square(*row, *col),
+                _ => "pass".to_string(),
+            };
+            json!({"type": "played", "side": side(s), "move": mv})
+        }
+        ServerMsg::Skip(s) => json!({"type": "skip", "side": side(s)}),
+        ServerMsg::Turn(s) => json!({"type": "turn", "side": side(s)}),
+        ServerMsg::Moves(moves) => {
+            let squares = moves
+                .iter()
+                .map(|&(row, col)| protocol::square(row, col))
+                .collect::<Vec<_>>();
+            json!({"type": "moves", "squares": squares})
+        }
+        ServerMsg::Clock { black, white } => {
+            json!({"type": "clock", "black": black, "white": white})
+        }
+        ServerMsg::Away { side: s, seconds } => {
+            json!({"type": "away", "side": side(s), "seconds": seconds})
+        }
+        ServerMsg::Back(s) => json!({"type": "back", "side": side(s)}),
+        ServerMsg::Over(outcome) => json!({
+            "type": "over",
+            "end": outcome.end.name(),
+            "winner": protocol::winner_name(outcome.winner),
+            "black": outcome.black,
+            "white": outcome.white,
+        }),
+        ServerMsg::Chat { name, text } => json!({"type": "chat", "name": name, "text": text}),
+        ServerMsg::Error(message) => json!({"type": "error", "message": message}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::SocketAddr;
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::WebSocket;
+
+    struct Browser(WebSocket<MaybeTlsStream<TcpStream>>);
+
+    impl Browser {
+        fn connect(addr: SocketAddr) -> Self {
+            let (socket, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+            if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+            }
+            Browser(socket)
+        }
+
+        fn send(&mut self, msg: Value) {
+            self.0.send(Message::Text(msg.to_string())).unwrap();
+        }
+
+        // Reads up to the first message of type `kind`, and returns it.
+        fn expect(&mut self, kind: &str) -> Value {
+            loop {
+                if let Message::Text(text) = self.0.read().unwrap() {
+                    let msg: Value = serde_json::from_str(&text).unwrap();
+                    if msg["type"] == kind {
+                        return msg;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn plays_a_browser_against_a_line_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let websocket = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (addr, ws) = (
+            listener.local_addr().unwrap(),
+            websocket.local_addr().unwrap(),
+        );
+        let clock = "5+2".parse().ok();
+        thread::spawn(move || {
+            server::run(
+                listener,
+                Some(websocket),
+                Duration::from_secs(5),
+                clock,
+                None,
+            )
+        });
+
+        let mut browser = Browser::connect(ws);
+        browser.send(json!({"type": "hello", "name": "web player"}));
+        assert_eq!(browser.expect("welcome")["name"], "web_player");
+        browser.send(json!({"type": "shout"}));
+        assert_eq!(
+            browser.expect("error")["message"],
+            "unknown message type `shout`"
+        );
+        browser.send(json!({"type": "create"}));
+        let game = browser.expect("game");
+        assert_eq!(game["side"], "black");
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut bob = stream;
+        writeln!(bob, "hello bob\njoin {}", game["game"]).unwrap();
+
+        assert_eq!(browser.expect("players")["white"], "bob");
+        let board = browser.expect("board");
+        assert_eq!(board["rows"][3], "---OX---");
+        assert_eq!(board["turn"], "black");
+        let clock = browser.expect("clock");
+        assert_eq!(clock["white"], 300_000);
+
+        browser.send(json!({"type": "moves"}));
+        let moves = browser.expect("moves");
+        assert_eq!(moves["squares"], json!(["d3", "c4", "f5", "e6"]));
+        browser.send(json!({"type": "move", "square": "a1"}));
+        assert_eq!(browser.expect("error")["message"], "no discs flipped");
+        browser.send(json!({"type": "move", "square": "d3"}));
+        assert_eq!(browser.expect("played")["move"], "d3");
+        assert_eq!(browser.expect("turn")["side"], "white");
+        assert!(browser.expect("clock")["black"].as_u64().unwrap() > 300_000);
+
+        let played = lines.find(|line| line.as_ref().unwrap().starts_with("played"));
+        assert_eq!(played.unwrap().unwrap(), "played black d3");
+        writeln!(bob, "resign").unwrap();
+        let over = browser.expect("over");
+        assert_eq!(
+            (&over["end"], &over["winner"]),
+            (&json!("resigned"), &json!("black"))
+        );
+    }
+}